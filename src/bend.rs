@@ -0,0 +1,118 @@
+//! Pitch bend applied on top of the frequency of each voice.
+
+use time;
+
+/// The 14-bit MIDI pitch bend value at which the pitch is not bent.
+pub const CENTER_14BIT: u16 = 8192;
+
+/// A pitch bend with a configurable range, smoothed over time in order to avoid the "zipper
+/// noise" caused by coarse steps in the bend.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PitchBend {
+    /// The bend in semitones when the bend is fully up (or down).
+    pub range: f32,
+    /// The time over which the bend moves most of the way towards a new target.
+    ///
+    /// A duration of `0.0` applies changes immediately.
+    pub smoothing_ms: time::Ms,
+    /// The bend that is being moved towards, from `-1.0` (fully down) to `1.0` (fully up).
+    pub target: f32,
+    /// The current bend, from `-1.0` (fully down) to `1.0` (fully up).
+    pub current: f32,
+}
+
+
+impl PitchBend {
+    /// Construct a centered `PitchBend` with the given range in semitones and no smoothing.
+    pub fn new(range: f32) -> Self {
+        PitchBend {
+            range,
+            smoothing_ms: time::Ms(0.0),
+            target: 0.0,
+            current: 0.0,
+        }
+    }
+
+    /// Set the target bend, from `-1.0` (fully down) to `1.0` (fully up).
+    pub fn set(&mut self, bend: f32) {
+        self.target = bend.clamp(-1.0, 1.0);
+    }
+
+    /// Set the target bend from a 14-bit MIDI pitch bend value, where `8192` is centered.
+    pub fn set_14bit(&mut self, value: u16) {
+        self.set(from_14bit(value));
+    }
+
+    /// The current bend in semitones.
+    pub fn semitones(&self) -> f32 {
+        self.current * self.range
+    }
+
+    /// Step the current bend one frame towards the target and return the bend in semitones.
+    pub fn next_semitones(&mut self, sample_hz: time::SampleHz) -> f32 {
+        let frames = self.smoothing_ms.samples(sample_hz);
+        if frames <= 0 {
+            self.current = self.target;
+        } else {
+            let coefficient = (-1.0 / frames as f32).exp();
+            self.current = self.target + (self.current - self.target) * coefficient;
+        }
+        self.semitones()
+    }
+}
+
+impl Default for PitchBend {
+    fn default() -> Self {
+        PitchBend::new(2.0)
+    }
+}
+
+
+/// Convert a 14-bit MIDI pitch bend value to a bend in the range `-1.0...1.0`.
+///
+/// Values above the center are scaled so that the maximum value `16383` is fully up.
+pub fn from_14bit(value: u16) -> f32 {
+    let offset = value.min(16383) as f32 - CENTER_14BIT as f32;
+    if offset < 0.0 {
+        offset / CENTER_14BIT as f32
+    } else {
+        offset / (CENTER_14BIT - 1) as f32
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{from_14bit, PitchBend};
+    use time;
+
+    #[test]
+    fn from_14bit_range() {
+        assert_eq!(from_14bit(0), -1.0);
+        assert_eq!(from_14bit(8192), 0.0);
+        assert_eq!(from_14bit(16383), 1.0);
+        assert_eq!(from_14bit(u16::MAX), 1.0);
+        assert_eq!(from_14bit(4096), -0.5);
+    }
+
+    #[test]
+    fn range() {
+        let mut bend = PitchBend::new(12.0);
+        bend.set_14bit(16383);
+        assert_eq!(bend.next_semitones(44_100.0), 12.0);
+        bend.set(-2.0);
+        assert_eq!(bend.next_semitones(44_100.0), -12.0);
+    }
+
+    #[test]
+    fn smoothing() {
+        let mut bend = PitchBend { smoothing_ms: time::Ms(10.0), ..PitchBend::new(2.0) };
+        bend.set(1.0);
+        let semitones: Vec<_> = (0..4410).map(|_| bend.next_semitones(44_100.0)).collect();
+        assert!(semitones[0] > 0.0 && semitones[0] < 0.01);
+        assert!(semitones.windows(2).all(|w| w[1] > w[0] && w[1] <= 2.0));
+        // After the smoothing time the bend has moved most of the way to its target.
+        assert!((semitones[440] - 2.0 * (1.0 - (-1.0f32).exp())).abs() < 0.01);
+        assert!((semitones[4409] - 2.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,258 @@
+use time;
+use unit::Playhead;
+use voice::NoteState;
+
+/// Types that describe the amplitude of a `Voice`'s note over the course of its playback.
+///
+/// The `Instrument` applies its `Envelope` to the velocity of each playing note once per frame.
+pub trait Envelope {
+    /// The stage and amplitude of a held note `playhead` frames after its `note_on`.
+    fn held(&self, playhead: Playhead, sample_hz: time::SampleHz) -> (NoteState, f32);
+
+    /// The amplitude of a note `release_playhead` frames after its `note_off`, where `amp` is the
+    /// amplitude of the note at the moment it was released.
+    ///
+    /// Returns `None` once the note has finished fading out.
+    fn released(&self,
+                amp: f32,
+                release_playhead: Playhead,
+                sample_hz: time::SampleHz) -> Option<f32>;
+}
+
+
+/// An attack, decay, sustain and release envelope.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Adsr {
+    /// A duration over which the amplitude of each note will fade in after `note_on`.
+    pub attack_ms: time::Ms,
+    /// The shape of the attack.
+    pub attack_curve: Curve,
+    /// A duration over which the amplitude of each note will fall from its peak to the `sustain`
+    /// level once the attack has completed.
+    pub decay_ms: time::Ms,
+    /// The shape of the decay.
+    pub decay_curve: Curve,
+    /// The amplitude (in the range `0.0...1.0`) at which each note is held after the decay.
+    pub sustain: f32,
+    /// A duration over which the amplitude of each note will fade out after `note_off`.
+    pub release_ms: time::Ms,
+    /// The shape of the release.
+    pub release_curve: Curve,
+}
+
+/// The shape with which an envelope segment moves from its start amplitude to its target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Curve {
+    /// The amplitude changes at a constant rate.
+    Linear,
+    /// The amplitude changes quickly at first and then settles towards its target, much like the
+    /// charging and discharging of an analogue envelope.
+    Exponential,
+    /// The amplitude changes slowly at first and then accelerates towards its target.
+    Logarithmic,
+    /// The amplitude eases out of its start and into its target.
+    SCurve,
+    /// A curve with the given curvature.
+    ///
+    /// `0.0` is linear, positive values bend the curve towards `Exponential` and negative values
+    /// bend the curve towards `Logarithmic`.
+    Custom(f32),
+}
+
+/// The duration and `Curve` of a single envelope segment.
+///
+/// A `Fade` may be created from a duration alone, in which case the `Curve` is `Linear`, or from
+/// a `(duration, Curve)` pair.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fade {
+    /// The duration of the segment.
+    pub ms: time::Ms,
+    /// The shape of the segment.
+    pub curve: Curve,
+}
+
+
+impl Adsr {
+    /// Construct a new `Adsr` envelope.
+    pub fn new<A, D, R>(attack: A, decay: D, sustain: f32, release: R) -> Self
+        where A: Into<Fade>,
+              D: Into<Fade>,
+              R: Into<Fade>,
+    {
+        let (attack, decay, release) = (attack.into(), decay.into(), release.into());
+        Adsr {
+            attack_ms: attack.ms,
+            attack_curve: attack.curve,
+            decay_ms: decay.ms,
+            decay_curve: decay.curve,
+            sustain,
+            release_ms: release.ms,
+            release_curve: release.curve,
+        }
+    }
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Adsr::new(time::Ms(0.0), time::Ms(0.0), 1.0, time::Ms(0.0))
+    }
+}
+
+
+impl Curve {
+    /// The curvature used by the `Exponential` and `Logarithmic` curves.
+    const CURVATURE: f32 = 5.0;
+
+    /// Maps the given linear progress through a segment (in the range `0.0...1.0`) to the curved
+    /// progress through the segment.
+    pub fn apply(&self, perc: f32) -> f32 {
+        // Bends the progress exponentially by the given curvature.
+        //
+        // A negative curvature mirrors the positive curve, as evaluating the exponential directly
+        // overflows for strongly negative curvatures.
+        fn bend(perc: f32, curvature: f32) -> f32 {
+            if curvature.abs() < f32::EPSILON {
+                perc
+            } else if curvature < 0.0 {
+                1.0 - bend(1.0 - perc, -curvature)
+            } else {
+                (1.0 - (-curvature * perc).exp()) / (1.0 - (-curvature).exp())
+            }
+        }
+
+        match *self {
+            Curve::Linear => perc,
+            Curve::Exponential => bend(perc, Curve::CURVATURE),
+            Curve::Logarithmic => bend(perc, -Curve::CURVATURE),
+            Curve::SCurve => perc * perc * (3.0 - 2.0 * perc),
+            Curve::Custom(curvature) => bend(perc, curvature),
+        }
+    }
+}
+
+
+impl From<time::Ms> for Fade {
+    fn from(ms: time::Ms) -> Self {
+        Fade { ms, curve: Curve::Linear }
+    }
+}
+
+impl From<time::calc::Ms> for Fade {
+    fn from(ms: time::calc::Ms) -> Self {
+        Fade::from(time::Ms(ms))
+    }
+}
+
+impl<T> From<(T, Curve)> for Fade
+    where T: Into<time::Ms>,
+{
+    fn from((ms, curve): (T, Curve)) -> Self {
+        Fade { ms: ms.into(), curve }
+    }
+}
+
+
+impl Envelope for () {
+    fn held(&self, _playhead: Playhead, _sample_hz: time::SampleHz) -> (NoteState, f32) {
+        (NoteState::Sustain, 1.0)
+    }
+    fn released(&self,
+                _amp: f32,
+                _release_playhead: Playhead,
+                _sample_hz: time::SampleHz) -> Option<f32> {
+        None
+    }
+}
+
+impl Envelope for Adsr {
+    fn held(&self, playhead: Playhead, sample_hz: time::SampleHz) -> (NoteState, f32) {
+        let attack = self.attack_ms.samples(sample_hz) as Playhead;
+        let decay = self.decay_ms.samples(sample_hz) as Playhead;
+        if playhead < attack {
+            let perc = playhead as f32 / attack as f32;
+            (NoteState::Attack, self.attack_curve.apply(perc))
+        } else if playhead < attack + decay {
+            let perc = (playhead - attack) as f32 / decay as f32;
+            (NoteState::Decay, 1.0 - (1.0 - self.sustain) * self.decay_curve.apply(perc))
+        } else {
+            (NoteState::Sustain, self.sustain)
+        }
+    }
+    fn released(&self,
+                amp: f32,
+                release_playhead: Playhead,
+                sample_hz: time::SampleHz) -> Option<f32> {
+        let release = self.release_ms.samples(sample_hz) as Playhead;
+        if release_playhead < release {
+            let perc = release_playhead as f32 / release as f32;
+            Some(amp * (1.0 - self.release_curve.apply(perc)))
+        } else {
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Curve;
+
+    const STEPS: usize = 100;
+
+    fn percs() -> Vec<f32> {
+        (0..STEPS + 1).map(|step| step as f32 / STEPS as f32).collect()
+    }
+
+    #[test]
+    fn curve_endpoints() {
+        let curves = [
+            Curve::Linear,
+            Curve::Exponential,
+            Curve::Logarithmic,
+            Curve::SCurve,
+            Curve::Custom(0.0),
+            Curve::Custom(1.0),
+            Curve::Custom(-1.0),
+        ];
+        for curve in &curves {
+            assert!(curve.apply(0.0).abs() < 1e-6, "{:?}", curve);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn linear() {
+        for perc in percs() {
+            assert_eq!(Curve::Linear.apply(perc), perc);
+        }
+    }
+
+    #[test]
+    fn exponential_and_logarithmic() {
+        for perc in percs() {
+            let exponential = Curve::Exponential.apply(perc);
+            let logarithmic = Curve::Logarithmic.apply(perc);
+            assert!(exponential >= perc - 1e-6);
+            assert!(logarithmic <= perc + 1e-6);
+            // The logarithmic curve mirrors the exponential one.
+            assert!((logarithmic - (1.0 - Curve::Exponential.apply(1.0 - perc))).abs() < 1e-6);
+        }
+        assert!(Curve::Exponential.apply(0.5) > 0.9);
+        assert!(Curve::Logarithmic.apply(0.5) < 0.1);
+    }
+
+    #[test]
+    fn extreme_custom_curvature() {
+        for &curvature in &[-1000.0, -200.0, -90.0, 90.0, 200.0, 1000.0] {
+            let curve = Curve::Custom(curvature);
+            let mut last = 0.0;
+            for perc in percs() {
+                let amp = curve.apply(perc);
+                assert!(amp.is_finite(), "{:?} at {}", curve, perc);
+                assert!(amp >= last && amp <= 1.0, "{:?} at {}", curve, perc);
+                last = amp;
+            }
+            assert_eq!(curve.apply(1.0), 1.0);
+        }
+    }
+}
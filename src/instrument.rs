@@ -1,10 +1,17 @@
-use mode::Mode;
-use note_freq::{NoteFreq, NoteFreqGenerator};
+use bend::{self, PitchBend};
+use envelope::{Adsr, Envelope, Fade};
+use midi;
+use mode::{Mode, NoteMatch, NoteOff, NoteOn, Pedals};
+use mpe;
+use note_freq::{DetuneRng, NoteFreq, NoteFreqGenerator};
+use pan;
 use pitch;
+use rand;
 use std;
 use time;
+use tuning;
 use voice::Voice;
-use unit::{NoteHz, NoteVelocity};
+use unit::{NoteHz, NoteId, NoteVelocity, Pan, Playhead};
 
 
 /// A performable `Instrument` type that converts `Note` events into a sequence of voices, each
@@ -16,10 +23,11 @@ use unit::{NoteHz, NoteVelocity};
 /// - Playback mode: Legato, Retrigger or Polyphonic.
 /// - Note on detuning.
 /// - Note on "interoplation" / frequency generation: Legato or Constant.
-/// - Sustained note warping: 
+/// - Note amplitude envelopes: ADSR or custom.
+/// - Sustained note warping:
 /// - Multi-channel audio processing.
 #[derive(Clone, Debug, PartialEq)]
-pub struct Instrument<M, NFG>
+pub struct Instrument<M, NFG, E=Adsr, G=DetuneRng>
     where NFG: NoteFreqGenerator,
 {
     /// The mode of note playback.
@@ -30,74 +38,217 @@ pub struct Instrument<M, NFG>
     pub voices: Vec<Voice<NFG::NoteFreq>>,
     /// The amount each voice's note_on should be detuned.
     pub detune: f32,
+    /// The random number generator used to detune each note.
+    pub rng: G,
     /// Note on "interoplation" / frequency generation: Legato or Constant.
     pub note_freq_gen: NFG,
-    /// A duration in frames over which the amplitude of each note will fade in after `note_on`.
-    pub attack_ms: time::Ms,
-    /// A duration in frames over which the amplitude of each note will fade out after `note_off`.
-    pub release_ms: time::Ms,
+    /// The amplitude envelope applied to the note of each voice.
+    pub envelope: E,
+    /// Converts the pan of each voice into the gain of each output channel.
+    pub pan_law: pan::Law,
+    /// If given, pans each note according to its pitch.
+    pub key_track: Option<pan::KeyTrack>,
+    /// The pitch bend applied on top of the frequency of every voice.
+    pub pitch_bend: PitchBend,
+    /// If given, MIDI messages on the zone's member channels are handled as MPE.
+    pub mpe_zone: Option<mpe::Zone>,
+    /// The expression most recently received on each MIDI channel of the `mpe_zone`.
+    pub mpe_expression: [mpe::Expression; 16],
+    /// How far the pressure applied to each note raises its velocity towards `1.0`, from `0.0`
+    /// (not at all) to `1.0` (all the way at full pressure).
+    pub pressure_to_velocity: f32,
+    /// The frequency of each MIDI key, used by `handle_midi`.
+    pub tuning: tuning::Table,
+    /// The rule by which a note-off given as a frequency is matched against the held notes.
+    pub note_match: NoteMatch,
+    /// The current state of the pedals that may hold notes after their keys are released.
+    pub pedals: Pedals,
+    /// The number of frames that have been yielded by the `Instrument` so far.
+    pub frame: Playhead,
+    /// The number of notes that have been started by the `Instrument` so far, used to determine
+    /// the order in which notes were played.
+    pub note_count: u64,
+    /// Events that have been scheduled to occur at some future `frame`, in order of occurrence.
+    pub events: std::collections::VecDeque<(Playhead, Event)>,
+    /// Parses raw MIDI bytes given to `handle_midi_bytes`, maintaining the running status.
+    pub midi_parser: midi::Parser,
+}
+
+/// A performance event that may be scheduled to occur at an exact frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Begin playback of a note with the given frequency and velocity.
+    NoteOn(NoteHz, NoteVelocity),
+    /// Stop playback of the note that was triggered with the matching frequency.
+    NoteOff(NoteHz),
+    /// Begin playback of a note identified by the given `NoteId`.
+    NoteOnWithId(NoteId, NoteHz, NoteVelocity),
+    /// Stop playback of the note that was triggered with the given `NoteId`.
+    NoteOffWithId(NoteId),
+    /// Set the pan of the note that was triggered with the given `NoteId`.
+    NotePan(NoteId, Pan),
+    /// Set the pitch bend in semitones of the note that was triggered with the given `NoteId`.
+    NoteBend(NoteId, f32),
+    /// Set the pressure of the note that was triggered with the given `NoteId`.
+    NotePressure(NoteId, f32),
+    /// Set the timbre of the note that was triggered with the given `NoteId`.
+    NoteTimbre(NoteId, f32),
+    /// Bend the pitch of every voice, from `-1.0` (fully down) to `1.0` (fully up).
+    PitchBend(f32),
+    /// Set the pressure of every playing note.
+    ChannelPressure(f32),
+    /// Retune the given MIDI key to the given frequency.
+    RetuneKey(midi::Key, NoteHz),
+    /// Press (`true`) or release (`false`) the sustain pedal.
+    SustainPedal(bool),
+    /// Press (`true`) or release (`false`) the sostenuto pedal.
+    SostenutoPedal(bool),
+    /// Stop playback and clear the current notes.
+    Stop,
 }
 
 /// An iterator that endlessly yields the next `FramePerVoice` for an `Instrument`.
-pub struct Frames<'a, NF: 'a> {
-    attack: u64,
-    release: u64,
-    voices: &'a mut [Voice<NF>],
+pub struct Frames<'a, M: 'a, NFG: 'a, E: 'a, G: 'a>
+    where NFG: NoteFreqGenerator,
+{
+    instrument: &'a mut Instrument<M, NFG, E, G>,
+    sample_hz: time::SampleHz,
 }
 
 /// Yields the amplitude and frequency of each voice for a single frame.
-pub struct FramePerVoice<'a, NF: 'a> {
-    attack: u64,
-    release: u64,
+pub struct FramePerVoice<'a, NF: 'a, E: 'a> {
+    bend: f32,
+    pressure_to_velocity: f32,
+    envelope: &'a E,
+    pan_law: pan::Law,
+    sample_hz: time::SampleHz,
     voices: std::slice::IterMut<'a, Voice<NF>>,
 }
 
+/// The output of a single voice for a single frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoiceFrame {
+    /// The velocity of the voice, shaped by the amplitude envelope.
+    pub vel: NoteVelocity,
+    /// The frequency of the voice in hertz.
+    pub hz: NoteHz,
+    /// The stereo position of the voice from `-1.0` (left) to `1.0` (right).
+    pub pan: Pan,
+    /// The law used to convert the `pan` into the gain of each channel.
+    pub pan_law: pan::Law,
+    /// The pressure applied to the voice's note, from `0.0` to `1.0`.
+    pub pressure: f32,
+    /// The timbre of the voice's note, from `0.0` to `1.0`.
+    pub timbre: f32,
+}
+
 
-impl<M, NFG> Instrument<M, NFG>
+impl<M, NFG> Instrument<M, NFG, Adsr, DetuneRng>
     where NFG: NoteFreqGenerator,
 {
 
     /// Construct a new `Instrument` of the given mode using the given note frequency generator.
+    ///
+    /// The `Instrument` uses an `Adsr` envelope by default. Use the `envelope` method to provide
+    /// a custom `Envelope`.
+    ///
+    /// Notes are detuned using a `DetuneRng` with the default seed. Use the `seed` method to
+    /// provide a different seed or the `rng` method to provide a custom random number generator.
     pub fn new(mode: M, note_freq_gen: NFG) -> Self {
         Instrument {
-            mode: mode,
+            mode,
             voices: vec![Voice::new()],
             detune: 0.0,
-            note_freq_gen: note_freq_gen,
-            attack_ms: time::Ms(0.0),
-            release_ms: time::Ms(0.0),
+            rng: DetuneRng::default(),
+            note_freq_gen,
+            envelope: Adsr::default(),
+            pan_law: pan::Law::default(),
+            key_track: None,
+            pitch_bend: PitchBend::default(),
+            mpe_zone: None,
+            mpe_expression: [mpe::Expression::default(); 16],
+            pressure_to_velocity: 0.0,
+            tuning: tuning::Table::default(),
+            note_match: NoteMatch::default(),
+            pedals: Pedals::default(),
+            frame: 0,
+            note_count: 0,
+            events: std::collections::VecDeque::new(),
+            midi_parser: midi::Parser::new(),
         }
     }
 
-    /// Build the Instrument with the given number of voices.
-    pub fn num_voices(mut self, num_voices: usize) -> Self {
-        self.set_num_voices(num_voices);
+}
+
+impl<M, NFG, G> Instrument<M, NFG, Adsr, G>
+    where NFG: NoteFreqGenerator,
+{
+
+    /// Set the note fades for the `Instrument`.
+    ///
+    /// Each fade may be given as a duration or as a `(duration, Curve)` pair.
+    pub fn fade<A, R>(self, attack: A, release: R) -> Self
+        where A: Into<Fade>,
+              R: Into<Fade>,
+    {
+        self.attack(attack).release(release)
+    }
+
+    /// Set the attack.
+    pub fn attack<A>(mut self, attack: A) -> Self
+        where A: Into<Fade>,
+    {
+        let Fade { ms, curve } = attack.into();
+        self.envelope.attack_ms = ms;
+        self.envelope.attack_curve = curve;
         self
     }
 
-    /// Set the note fades for the `Instrument` in frames.
-    pub fn fade<A, R>(mut self, attack: A, release: R) -> Self
-        where A: Into<time::Ms>,
-              R: Into<time::Ms>,
+    /// Set the decay.
+    pub fn decay<D>(mut self, decay: D) -> Self
+        where D: Into<Fade>,
     {
-        self.attack_ms = attack.into();
-        self.release_ms = release.into();
+        let Fade { ms, curve } = decay.into();
+        self.envelope.decay_ms = ms;
+        self.envelope.decay_curve = curve;
         self
     }
 
-    /// Set the attack.
-    pub fn attack<A>(mut self, attack: A) -> Self
-        where A: Into<time::Ms>,
+    /// Set the sustain level.
+    pub fn sustain(mut self, sustain: f32) -> Self {
+        self.envelope.sustain = sustain;
+        self
+    }
+
+    /// Set the attack, decay, sustain and release of the `Instrument`'s note envelope.
+    pub fn adsr<A, D, R>(mut self, attack: A, decay: D, sustain: f32, release: R) -> Self
+        where A: Into<Fade>,
+              D: Into<Fade>,
+              R: Into<Fade>,
     {
-        self.attack_ms = attack.into();
+        self.envelope = Adsr::new(attack, decay, sustain, release);
         self
     }
 
     /// Set the release.
     pub fn release<R>(mut self, release: R) -> Self
-        where R: Into<time::Ms>,
+        where R: Into<Fade>,
     {
-        self.release_ms = release.into();
+        let Fade { ms, curve } = release.into();
+        self.envelope.release_ms = ms;
+        self.envelope.release_curve = curve;
+        self
+    }
+
+}
+
+impl<M, NFG, E, G> Instrument<M, NFG, E, G>
+    where NFG: NoteFreqGenerator,
+{
+
+    /// Build the Instrument with the given number of voices.
+    pub fn num_voices(mut self, num_voices: usize) -> Self {
+        self.set_num_voices(num_voices);
         self
     }
 
@@ -115,6 +266,144 @@ impl<M, NFG> Instrument<M, NFG>
         self
     }
 
+    /// Set the law used to convert the pan of each voice into the gain of each output channel.
+    pub fn pan_law(mut self, pan_law: pan::Law) -> Self {
+        self.pan_law = pan_law;
+        self
+    }
+
+    /// Pan each note according to its pitch.
+    pub fn key_track(mut self, key_track: pan::KeyTrack) -> Self {
+        self.key_track = Some(key_track);
+        self
+    }
+
+    /// Handle MIDI messages on the member channels of the given zone as MPE.
+    ///
+    /// Notes played on a member channel are identified by `mpe::note_id`, while the pitch bend,
+    /// channel pressure and timbre (CC 74) of each member channel are applied to its notes.
+    pub fn mpe_zone(mut self, zone: mpe::Zone) -> Self {
+        self.mpe_zone = Some(zone);
+        self
+    }
+
+    /// Raise the velocity of each note towards `1.0` as pressure is applied to it, allowing held
+    /// notes to swell.
+    ///
+    /// An `amount` of `1.0` raises the velocity all the way to `1.0` at full pressure, while `0.0`
+    /// leaves the velocity unaffected.
+    pub fn pressure_to_velocity(mut self, amount: f32) -> Self {
+        self.pressure_to_velocity = amount.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Convert the key of each MIDI note to a frequency using the given tuning `Table`.
+    pub fn tuning(mut self, tuning: tuning::Table) -> Self {
+        self.tuning = tuning;
+        self
+    }
+
+    /// Set the rule by which a note-off given as a frequency is matched against the held notes.
+    pub fn note_match(mut self, note_match: NoteMatch) -> Self {
+        self.note_match = note_match;
+        self
+    }
+
+    /// Match a note-off given as a frequency against the held notes within the given number of
+    /// cents.
+    pub fn note_off_tolerance(self, cents: f32) -> Self {
+        self.note_match(NoteMatch::Cents(cents))
+    }
+
+    /// Set the range of the pitch bend in semitones, reached when the bend is fully up or down.
+    pub fn pitch_bend_range(mut self, semitones: f32) -> Self {
+        self.pitch_bend.range = semitones;
+        self
+    }
+
+    /// Smooth changes to the pitch bend over the given duration in order to avoid zipper noise.
+    pub fn pitch_bend_smoothing<T>(mut self, smoothing: T) -> Self
+        where T: Into<time::Ms>,
+    {
+        self.pitch_bend.smoothing_ms = smoothing.into();
+        self
+    }
+
+    /// Seed the random number generator used to detune each note.
+    ///
+    /// Two renders of the same performance using the same seed produce identical detuning.
+    pub fn seed(mut self, seed: u64) -> Self
+        where G: rand::SeedableRng<u64>,
+    {
+        self.rng.reseed(seed);
+        self
+    }
+
+    /// Convert `Self` into a new `Instrument` with the given random number generator, used to
+    /// detune each note.
+    pub fn rng<G2>(self, rng: G2) -> Instrument<M, NFG, E, G2>
+        where G2: rand::Rng,
+    {
+        let Instrument {
+            mode, voices, detune, note_freq_gen, envelope, pan_law, key_track, pitch_bend,
+            mpe_zone, mpe_expression, pressure_to_velocity, tuning, note_match, pedals, frame,
+            note_count, events, midi_parser, ..
+        } = self;
+        Instrument {
+            mode,
+            voices,
+            detune,
+            rng,
+            note_freq_gen,
+            envelope,
+            pan_law,
+            key_track,
+            pitch_bend,
+            mpe_zone,
+            mpe_expression,
+            pressure_to_velocity,
+            tuning,
+            note_match,
+            pedals,
+            frame,
+            note_count,
+            events,
+            midi_parser,
+        }
+    }
+
+    /// Convert `Self` into a new `Instrument` with the given amplitude `Envelope`.
+    pub fn envelope<E2>(self, envelope: E2) -> Instrument<M, NFG, E2, G>
+        where E2: Envelope,
+    {
+        let Instrument {
+            mode, voices, detune, rng, note_freq_gen, pan_law, key_track, pitch_bend,
+            mpe_zone, mpe_expression, pressure_to_velocity, tuning, note_match, pedals, frame,
+            note_count, events, midi_parser, ..
+        } = self;
+        Instrument {
+            mode,
+            voices,
+            detune,
+            rng,
+            note_freq_gen,
+            envelope,
+            pan_law,
+            key_track,
+            pitch_bend,
+            mpe_zone,
+            mpe_expression,
+            pressure_to_velocity,
+            tuning,
+            note_match,
+            pedals,
+            frame,
+            note_count,
+            events,
+            midi_parser,
+        }
+    }
+
     /// Set the number of voices that the Instrument shall use.
     pub fn set_num_voices(&mut self, num_voices: usize) {
         if num_voices == 0 {
@@ -123,7 +412,7 @@ impl<M, NFG> Instrument<M, NFG>
             let len = self.voices.len();
             if len < num_voices {
                 let last_voice = self.voices[len-1].clone();
-                self.voices.extend(std::iter::repeat(last_voice).take(num_voices - len));
+                self.voices.resize(num_voices, last_voice);
             } else if len > num_voices {
                 self.voices.truncate(num_voices);
             }
@@ -137,25 +426,208 @@ impl<M, NFG> Instrument<M, NFG>
 
     /// Begin playback of a note. Instrument will try to use a free `Voice` to do this.
     ///
-    /// If no `Voice`s are free, the one playing the oldest note will be chosen to play the new
+    /// If no `Voice`s are free, the `Mode` decides which (if any) will be stolen to play the new
     /// note instead.
     #[inline]
     pub fn note_on<T>(&mut self, note_hz: T, note_vel: NoteVelocity)
         where M: Mode,
+              G: rand::Rng,
               T: Into<pitch::Hz>
     {
-        let Instrument { detune, ref note_freq_gen, ref mut mode, ref mut voices, .. } = *self;
-        mode.note_on(note_hz.into().hz(), note_vel, detune, note_freq_gen, voices);
+        self.play_note(None, note_hz.into().hz(), note_vel);
     }
 
-    /// Stop playback of the note that was triggered with the matching frequency.
+    /// Begin playback of a note identified by the given `note_id`.
+    ///
+    /// The note may later be released by passing the same `note_id` to `note_off_with_id`, which
+    /// releases exactly this note regardless of its frequency. The `note_id` may be any value that
+    /// is unique among the currently held notes, except for the ids from `midi::FIRST_NOTE_ID`
+    /// onwards which are reserved for the notes played by `handle_midi`. Notes with a reserved id
+    /// are ignored.
+    #[inline]
+    pub fn note_on_with_id<T>(&mut self, note_id: NoteId, note_hz: T, note_vel: NoteVelocity)
+        where M: Mode,
+              G: rand::Rng,
+              T: Into<pitch::Hz>
+    {
+        if !midi::is_note_id(note_id) {
+            self.play_note(Some(note_id), note_hz.into().hz(), note_vel);
+        }
+    }
+
+    /// Pass a `note_on` for the given note to the `Mode`, stamping it with the `note_count`.
+    fn play_note(&mut self, id: Option<NoteId>, hz: NoteHz, vel: NoteVelocity)
+        where M: Mode,
+              G: rand::Rng,
+    {
+        let pan = self.key_track.map_or(0.0, |key_track| key_track.pan(hz));
+        let note_on = NoteOn { hz, id, vel, pan, order: self.note_count };
+        self.note_count += 1;
+        let Instrument {
+            detune, note_match, ref note_freq_gen, ref mut rng, ref mut mode, ref mut voices, ..
+        } = *self;
+        mode.note_on(note_on, note_match, detune, note_freq_gen, rng, voices);
+    }
+
+    /// Stop playback of the note that was triggered with the matching frequency, as determined by
+    /// the `note_match` rule.
     #[inline]
     pub fn note_off<T>(&mut self, note_hz: T)
         where M: Mode,
+              G: rand::Rng,
               T: Into<pitch::Hz>
     {
-        let Instrument { detune, ref note_freq_gen,  ref mut mode, ref mut voices, .. } = *self;
-        mode.note_off(note_hz.into().hz(), detune, note_freq_gen, voices);
+        let Instrument {
+            detune, note_match, pedals, ref note_freq_gen, ref mut rng, ref mut mode,
+            ref mut voices, ..
+        } = *self;
+        let note_off = NoteOff::Hz(note_hz.into().hz(), note_match);
+        mode.note_off(note_off, pedals, detune, note_freq_gen, rng, voices);
+    }
+
+    /// Stop playback of the note that was triggered with the given `note_id`.
+    #[inline]
+    pub fn note_off_with_id(&mut self, note_id: NoteId)
+        where M: Mode,
+              G: rand::Rng,
+    {
+        let Instrument {
+            detune, pedals, ref note_freq_gen, ref mut rng, ref mut mode, ref mut voices, ..
+        } = *self;
+        mode.note_off(NoteOff::Id(note_id), pedals, detune, note_freq_gen, rng, voices);
+    }
+
+    /// Set the pan of the note that was triggered with the given `note_id`, from `-1.0` (left) to
+    /// `1.0` (right).
+    pub fn set_note_pan(&mut self, note_id: NoteId, pan: Pan) {
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if note.id == Some(note_id) {
+                    note.pan = pan::clamp(pan);
+                }
+            }
+        }
+    }
+
+    /// Set the pitch bend in semitones of the note that was triggered with the given `note_id`.
+    ///
+    /// The note's bend is applied on top of the `pitch_bend` of every voice.
+    pub fn set_note_bend(&mut self, note_id: NoteId, semitones: f32) {
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if note.id == Some(note_id) {
+                    note.bend = semitones;
+                }
+            }
+        }
+    }
+
+    /// Set the pressure of the note that was triggered with the given `note_id`, from `0.0` to
+    /// `1.0`.
+    pub fn set_note_pressure(&mut self, note_id: NoteId, pressure: f32) {
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if note.id == Some(note_id) {
+                    note.pressure = pressure.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Set the timbre of the note that was triggered with the given `note_id`, from `0.0` to
+    /// `1.0`.
+    pub fn set_note_timbre(&mut self, note_id: NoteId, timbre: f32) {
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if note.id == Some(note_id) {
+                    note.timbre = timbre.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Set the pressure of every playing note, from `0.0` to `1.0`.
+    pub fn channel_pressure(&mut self, pressure: f32) {
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if note.state.is_playing() {
+                    note.pressure = pressure.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Retune the given MIDI key to the given frequency.
+    ///
+    /// The frequency is stored in the `tuning` for subsequent notes, while the notes that are
+    /// already sounding with the key (including those held by a `Mono` note stack) are retuned
+    /// immediately.
+    pub fn retune_key(&mut self, key: midi::Key, hz: NoteHz)
+        where M: Mode,
+    {
+        self.tuning.set_hz(key, Some(hz));
+        let is_key = |id: Option<NoteId>| id.is_some_and(|id| note_key(id) == Some(key));
+        self.mode.retune(|id| if is_key(id) { Some(hz) } else { None });
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                if is_key(note.id) {
+                    note.retune = 1200.0 * (hz / note.hz).log2();
+                }
+            }
+        }
+    }
+
+    /// Bend the pitch of every voice, from `-1.0` (fully down) to `1.0` (fully up).
+    ///
+    /// The bend is scaled by the `pitch_bend_range` and applied on top of the frequency produced
+    /// by each voice's `NoteFreq`.
+    #[inline]
+    pub fn pitch_bend(&mut self, bend: f32) {
+        self.pitch_bend.set(bend);
+    }
+
+    /// Bend the pitch of every voice by the given 14-bit MIDI pitch bend value, where `8192` is
+    /// centered.
+    #[inline]
+    pub fn pitch_bend_14bit(&mut self, value: u16) {
+        self.pitch_bend.set_14bit(value);
+    }
+
+    /// Press (`true`) or release (`false`) the sustain pedal.
+    ///
+    /// While the sustain pedal is down, notes whose keys are released continue to play until the
+    /// pedal is released.
+    #[inline]
+    pub fn sustain_pedal(&mut self, down: bool)
+        where M: Mode,
+    {
+        self.pedals.sustain = down;
+        self.mode.pedals(self.pedals, &mut self.voices);
+    }
+
+    /// Press (`true`) or release (`false`) the sostenuto pedal.
+    ///
+    /// Pressing the sostenuto pedal latches the notes whose keys are held at that moment. Latched
+    /// notes continue to play after their keys are released until the pedal is released, while
+    /// notes played after the pedal was pressed are released as normal.
+    #[inline]
+    pub fn sostenuto_pedal(&mut self, down: bool)
+        where M: Mode,
+    {
+        if down && !self.pedals.sostenuto {
+            for voice in self.voices.iter_mut() {
+                voice.latch();
+            }
+        }
+        self.pedals.sostenuto = down;
+        self.mode.pedals(self.pedals, &mut self.voices);
+        if !down {
+            for voice in self.voices.iter_mut() {
+                if let Some(ref mut note) = voice.note {
+                    note.latched = false;
+                }
+            }
+        }
     }
 
     /// Stop playback and clear the current notes.
@@ -169,24 +641,221 @@ impl<M, NFG> Instrument<M, NFG>
         }
     }
 
+    /// Schedule the given `event` to occur `frames` frames from now.
+    ///
+    /// The event is applied at the beginning of the frame at which it is scheduled, allowing a
+    /// whole buffer of events to be rendered by a single `Frames`. Events scheduled for the same
+    /// frame are applied in the order in which they were scheduled.
+    pub fn schedule(&mut self, frames: Playhead, event: Event) {
+        let frame = self.frame + frames;
+        let idx = self.events.iter().position(|&(f, _)| f > frame).unwrap_or(self.events.len());
+        self.events.insert(idx, (frame, event));
+    }
+
+    /// Schedule a `note_on` to occur `frames` frames from now.
+    pub fn note_on_at<T>(&mut self, frames: Playhead, note_hz: T, note_vel: NoteVelocity)
+        where T: Into<pitch::Hz>,
+    {
+        self.schedule(frames, Event::NoteOn(note_hz.into().hz(), note_vel));
+    }
+
+    /// Schedule a `note_off` to occur `frames` frames from now.
+    pub fn note_off_at<T>(&mut self, frames: Playhead, note_hz: T)
+        where T: Into<pitch::Hz>,
+    {
+        self.schedule(frames, Event::NoteOff(note_hz.into().hz()));
+    }
+
+    /// Apply the given `event` immediately.
+    pub fn handle_event(&mut self, event: Event)
+        where M: Mode,
+              G: rand::Rng,
+    {
+        match event {
+            Event::NoteOn(hz, vel) => self.note_on(pitch::Hz(hz), vel),
+            Event::NoteOff(hz) => self.note_off(pitch::Hz(hz)),
+            Event::NoteOnWithId(id, hz, vel) => self.note_on_with_id(id, pitch::Hz(hz), vel),
+            Event::NoteOffWithId(id) => self.note_off_with_id(id),
+            Event::NotePan(id, pan) => self.set_note_pan(id, pan),
+            Event::NoteBend(id, semitones) => self.set_note_bend(id, semitones),
+            Event::NotePressure(id, pressure) => self.set_note_pressure(id, pressure),
+            Event::NoteTimbre(id, timbre) => self.set_note_timbre(id, timbre),
+            Event::PitchBend(bend) => self.pitch_bend(bend),
+            Event::ChannelPressure(pressure) => self.channel_pressure(pressure),
+            Event::RetuneKey(key, hz) => self.retune_key(key, hz),
+            Event::SustainPedal(down) => self.sustain_pedal(down),
+            Event::SostenutoPedal(down) => self.sostenuto_pedal(down),
+            Event::Stop => self.stop(),
+        }
+    }
+
+    /// Handle the given MIDI channel message.
+    ///
+    /// - `NoteOn` and `NoteOff` messages play and release a note identified by the `midi::note_id`
+    ///   of the channel and key with the frequency of the key given by the `tuning`, so that the
+    ///   same key played on different channels is played as separate notes.
+    ///   A `NoteOn` with a velocity of `0` is a `note_off`, while a `NoteOn` for a key without a
+    ///   frequency is ignored.
+    /// - `PolyPressure` messages trigger `set_note_pressure` for the note's `NoteId`, while
+    ///   `ChannelPressure` messages trigger `channel_pressure`.
+    /// - `PitchBend` messages trigger `pitch_bend_14bit`.
+    /// - The sustain (CC 64) and sostenuto (CC 66) pedals are pressed by values of `64` and above
+    ///   and released otherwise.
+    /// - "All Sound Off" (CC 120) and "All Notes Off" (CC 123) `stop` the `Instrument`.
+    ///
+    /// If an `mpe_zone` is given, messages on its member channels are handled by
+    /// `handle_mpe_midi` instead, while messages on its master channel are handled as above.
+    ///
+    /// All other messages are ignored.
+    pub fn handle_midi(&mut self, message: midi::Message)
+        where M: Mode,
+              G: rand::Rng,
+    {
+        if let Some(zone) = self.mpe_zone {
+            if zone.is_member(message.channel()) {
+                return self.handle_mpe_midi(zone, message);
+            }
+        }
+        match message {
+            midi::Message::NoteOn(channel, key, 0) | midi::Message::NoteOff(channel, key, _) =>
+                self.note_off_with_id(midi::note_id(channel, key)),
+            midi::Message::NoteOn(channel, key, vel) => {
+                if let Some(hz) = self.tuning.hz(key) {
+                    let vel = midi::value_to_velocity(vel);
+                    self.play_note(Some(midi::note_id(channel, key)), hz, vel);
+                }
+            },
+            midi::Message::PolyPressure(channel, key, value) =>
+                self.set_note_pressure(midi::note_id(channel, key), value as f32 / 127.0),
+            midi::Message::ChannelPressure(_, value) => self.channel_pressure(value as f32 / 127.0),
+            midi::Message::PitchBend(_, value) => self.pitch_bend_14bit(value),
+            midi::Message::ControlChange(_, midi::SUSTAIN_PEDAL, value) =>
+                self.sustain_pedal(midi::is_pedal_down(value)),
+            midi::Message::ControlChange(_, midi::SOSTENUTO_PEDAL, value) =>
+                self.sostenuto_pedal(midi::is_pedal_down(value)),
+            midi::Message::ControlChange(_, midi::ALL_SOUND_OFF, _) |
+            midi::Message::ControlChange(_, midi::ALL_NOTES_OFF, _) => self.stop(),
+            _ => (),
+        }
+    }
+
+    /// Handle the given MIDI message received on a member channel of the given MPE `zone`.
+    ///
+    /// - `NoteOn` and `NoteOff` messages play and release a note identified by the `mpe::note_id`
+    ///   of the channel and key.
+    /// - `PitchBend`, `ChannelPressure` and timbre (CC 74) messages set the expression of the
+    ///   channel, which is applied to every note played on the channel including those played
+    ///   later.
+    ///
+    /// All other messages are ignored.
+    fn handle_mpe_midi(&mut self, zone: mpe::Zone, message: midi::Message)
+        where M: Mode,
+              G: rand::Rng,
+    {
+        match message {
+            midi::Message::NoteOn(channel, key, 0) | midi::Message::NoteOff(channel, key, _) =>
+                self.note_off_with_id(mpe::note_id(channel, key)),
+            midi::Message::NoteOn(channel, key, vel) => {
+                let hz = match self.tuning.hz(key) {
+                    Some(hz) => hz,
+                    None => return,
+                };
+                self.play_note(Some(mpe::note_id(channel, key)), hz, midi::value_to_velocity(vel));
+            },
+            midi::Message::PitchBend(channel, value) => {
+                let semitones = bend::from_14bit(value) * zone.bend_range;
+                self.mpe_expression[channel as usize].bend = semitones;
+            },
+            midi::Message::ChannelPressure(channel, value) =>
+                self.mpe_expression[channel as usize].pressure = value as f32 / 127.0,
+            midi::Message::ControlChange(channel, mpe::TIMBRE, value) =>
+                self.mpe_expression[channel as usize].timbre = value as f32 / 127.0,
+            _ => return,
+        }
+
+        // Notes may have moved between voices, so reapply the expression of every member channel.
+        for voice in self.voices.iter_mut() {
+            if let Some(ref mut note) = voice.note {
+                let channel = match note.id {
+                    Some(id) if midi::is_note_id(id) && zone.is_member(mpe::channel(id)) =>
+                        mpe::channel(id),
+                    _ => continue,
+                };
+                let expression = self.mpe_expression[channel as usize];
+                note.bend = expression.bend;
+                note.pressure = expression.pressure;
+                note.timbre = expression.timbre;
+            }
+        }
+    }
+
+    /// Handle the given MIDI system exclusive message.
+    ///
+    /// MIDI Tuning Standard bulk dumps and single note tuning changes trigger `retune_key` for
+    /// each key they retune. See `tuning::parse_mts`.
+    ///
+    /// All other messages are ignored.
+    pub fn handle_sysex(&mut self, sysex: &[u8])
+        where M: Mode,
+    {
+        if let Some(retunings) = tuning::parse_mts(sysex) {
+            for (key, hz) in retunings {
+                self.retune_key(key, hz);
+            }
+        }
+    }
+
+    /// Parse the given raw MIDI bytes and handle each complete channel message and system
+    /// exclusive message in turn.
+    ///
+    /// Running status is maintained between calls, so a message may be split across calls.
+    pub fn handle_midi_bytes(&mut self, bytes: &[u8])
+        where M: Mode,
+              G: rand::Rng,
+    {
+        for &byte in bytes {
+            if let Some(message) = self.midi_parser.parse(byte) {
+                self.handle_midi(message);
+            }
+            if let Some(sysex) = self.midi_parser.take_sysex() {
+                self.handle_sysex(&sysex);
+            }
+        }
+    }
+
     /// Provides an `Iterator` like type that consecutively yields the `next_frame_per_voice` for
     /// the given `sample_hz`.
     #[inline]
-    pub fn frames(&mut self, sample_hz: time::SampleHz) -> Frames<NFG::NoteFreq> {
+    pub fn frames(&mut self, sample_hz: time::SampleHz) -> Frames<'_, M, NFG, E, G> {
         Frames {
-            attack: self.attack_ms.samples(sample_hz) as u64,
-            release: self.release_ms.samples(sample_hz) as u64,
-            voices: &mut self.voices,
+            instrument: self,
+            sample_hz,
         }
     }
 
     /// Produces an Iterator that yields the amplitude and frequency of each voice for the next
     /// frame.
+    ///
+    /// Any events scheduled to occur at or before the frame are applied first, after which the
+    /// pitch bend is stepped towards its target.
     #[inline]
-    pub fn frame_per_voice(&mut self, sample_hz: time::SampleHz) -> FramePerVoice<NFG::NoteFreq> {
+    pub fn frame_per_voice(&mut self, sample_hz: time::SampleHz) -> FramePerVoice<'_, NFG::NoteFreq, E>
+        where M: Mode,
+              G: rand::Rng,
+    {
+        while self.events.front().is_some_and(|&(frame, _)| frame <= self.frame) {
+            if let Some((_, event)) = self.events.pop_front() {
+                self.handle_event(event);
+            }
+        }
+        self.frame += 1;
+        let bend = self.pitch_bend.next_semitones(sample_hz);
         FramePerVoice {
-            attack: self.attack_ms.samples(sample_hz) as u64,
-            release: self.release_ms.samples(sample_hz) as u64,
+            bend,
+            pressure_to_velocity: self.pressure_to_velocity,
+            envelope: &self.envelope,
+            pan_law: self.pan_law,
+            sample_hz,
             voices: self.voices.iter_mut(),
         }
     }
@@ -194,28 +863,39 @@ impl<M, NFG> Instrument<M, NFG>
 }
 
 
-impl<'a, NF> Frames<'a, NF>
-    where NF: NoteFreq,
+/// The MIDI key of the note with the given `NoteId`, or `None` if the note was not played by
+/// `handle_midi`.
+fn note_key(note_id: NoteId) -> Option<midi::Key> {
+    if midi::is_note_id(note_id) {
+        Some(midi::key(note_id))
+    } else {
+        None
+    }
+}
+
+
+impl<'a, M, NFG, E, G> Frames<'a, M, NFG, E, G>
+    where M: Mode,
+          NFG: NoteFreqGenerator,
+          E: Envelope,
+          G: rand::Rng,
 {
     /// Counts the number of `Voice`s that are currently playing a note.
     #[inline]
     pub fn num_active_voices(&self) -> usize {
-        self.voices.iter().filter(|v| v.note.is_some()).count()
+        self.instrument.voices.iter().filter(|v| v.note.is_some()).count()
     }
 
     /// Yields the next `FramePerVoice` for the `Instrument`.
     #[inline]
-    pub fn next_frame_per_voice(&mut self) -> FramePerVoice<NF> {
-        FramePerVoice {
-            attack: self.attack,
-            release: self.release,
-            voices: self.voices.iter_mut(),
-        }
+    pub fn next_frame_per_voice(&mut self) -> FramePerVoice<'_, NFG::NoteFreq, E> {
+        self.instrument.frame_per_voice(self.sample_hz)
     }
 }
 
-impl<'a, NF> FramePerVoice<'a, NF>
+impl<'a, NF, E> FramePerVoice<'a, NF, E>
     where NF: NoteFreq,
+          E: Envelope,
 {
     /// The velocity and frequency in hertz of the next `Voice` at the current frame.
     ///
@@ -224,15 +904,37 @@ impl<'a, NF> FramePerVoice<'a, NF>
     /// Returns `None` if there are no more voices for the current frame.
     #[inline]
     pub fn next_voice_vel_hz(&mut self) -> Option<Option<(NoteVelocity, NoteHz)>> {
-        let FramePerVoice { ref mut voices, attack, release } = *self;
-        voices.next().map(|voice| voice.next_vel_hz(attack, release).map(|(vel, hz)| {
-            (vel, hz)
-        }))
+        let FramePerVoice {
+            ref mut voices, bend, pressure_to_velocity, envelope, sample_hz, ..
+        } = *self;
+        voices.next().map(|voice| {
+            voice.next_vel_hz(envelope, bend, pressure_to_velocity, sample_hz)
+        })
+    }
+
+    /// The `VoiceFrame` of the next `Voice` at the current frame, including its stereo position.
+    ///
+    /// Returns `Some(None)` if the voice exists but is not currently playing a note.
+    ///
+    /// Returns `None` if there are no more voices for the current frame.
+    #[inline]
+    pub fn next_voice_frame(&mut self) -> Option<Option<VoiceFrame>> {
+        let FramePerVoice {
+            ref mut voices, bend, pressure_to_velocity, envelope, pan_law, sample_hz
+        } = *self;
+        voices.next().map(|voice| {
+            let pan = voice.note.as_ref().map_or(0.0, |note| pan::clamp(note.pan + note.spread));
+            let (pressure, timbre) = voice.note.as_ref()
+                .map_or((0.0, 0.0), |note| (note.pressure, note.timbre));
+            voice.next_vel_hz(envelope, bend, pressure_to_velocity, sample_hz)
+                .map(|(vel, hz)| VoiceFrame { vel, hz, pan, pan_law, pressure, timbre })
+        })
     }
 }
 
-impl<'a, NF> Iterator for FramePerVoice<'a, NF>
+impl<'a, NF, E> Iterator for FramePerVoice<'a, NF, E>
     where NF: NoteFreq,
+          E: Envelope,
 {
     type Item = Option<(NoteVelocity, NoteHz)>;
     #[inline]
@@ -240,3 +942,205 @@ impl<'a, NF> Iterator for FramePerVoice<'a, NF>
         self.next_voice_vel_hz()
     }
 }
+
+impl VoiceFrame {
+    /// The gain of the given `channel` of an output with `num_channels` channels spread evenly
+    /// from left to right.
+    ///
+    /// The velocity of the voice is not included.
+    #[inline]
+    pub fn channel_gain(&self, channel: usize, num_channels: usize) -> f32 {
+        pan::channel_gain(self.pan, self.pan_law, channel, num_channels)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use midi;
+    use mode::{Mono, Poly};
+    use mpe;
+    use super::Instrument;
+
+    const SAMPLE_HZ: f64 = 44_100.0;
+
+    /// Render frames until the first voice falls silent, returning the number of frames rendered
+    /// and the peak velocity of the first voice.
+    fn render<M>(instrument: &mut Instrument<M, ()>) -> (usize, f32)
+        where M: ::Mode,
+    {
+        let mut frames = 0;
+        let mut peak: f32 = 0.0;
+        while let Some(Some((vel, _))) = instrument.frame_per_voice(SAMPLE_HZ).next() {
+            frames += 1;
+            peak = peak.max(vel);
+        }
+        (frames, peak)
+    }
+
+    #[test]
+    fn midi_channels_do_not_collide() {
+        let mut instrument = Instrument::new(Poly::default(), ()).num_voices(2);
+        instrument.handle_midi_bytes(&[0x90, 60, 100, 0x91, 60, 100, 0x81, 60, 0]);
+        let ids: Vec<_> = instrument.voices.iter()
+            .filter_map(|voice| voice.note.as_ref())
+            .filter(|note| note.state.is_playing())
+            .map(|note| note.id)
+            .collect();
+        assert_eq!(ids, vec![Some(midi::note_id(0, 60))]);
+    }
+
+    #[test]
+    fn midi_note_ids_are_reserved() {
+        let mut instrument = Instrument::new(Poly::default(), ()).num_voices(2);
+        instrument.note_on_with_id(midi::note_id(0, 60), 440.0, 1.0);
+        assert!(!instrument.is_active());
+        instrument.note_on_with_id(60, 440.0, 1.0);
+        instrument.handle_midi_bytes(&[0x90, 60, 100]);
+        let ids: Vec<_> = instrument.voices.iter()
+            .filter_map(|voice| voice.note.as_ref().and_then(|note| note.id))
+            .collect();
+        assert_eq!(ids, vec![60, midi::note_id(0, 60)]);
+    }
+
+    #[test]
+    fn mpe_expression_ignores_user_note_ids() {
+        let mut instrument = Instrument::new(Poly::default(), ())
+            .num_voices(2)
+            .mpe_zone(mpe::Zone::lower(15));
+        instrument.note_on_with_id(1 << 7 | 60, 440.0, 1.0);
+        instrument.handle_midi_bytes(&[0x91, 60, 100, 0xE1, 0x7F, 0x7F, 0xD1, 127]);
+        let expression: Vec<_> = instrument.voices.iter()
+            .filter_map(|voice| voice.note.as_ref())
+            .map(|note| (note.bend > 0.0, note.pressure))
+            .collect();
+        assert_eq!(expression, vec![(false, 0.0), (true, 1.0)]);
+    }
+
+    #[test]
+    fn same_seed_same_detune() {
+        let detuned_hz = |seed| {
+            let mut instrument = Instrument::new(Poly::default(), ())
+                .num_voices(4)
+                .detune(0.5)
+                .seed(seed);
+            for &hz in &[220.0, 330.0, 440.0, 550.0] {
+                instrument.note_on(hz, 1.0);
+            }
+            frame_hz(&mut instrument)
+        };
+        let hz = detuned_hz(7);
+        assert_eq!(hz.len(), 4);
+        assert!(hz != vec![220.0, 330.0, 440.0, 550.0]);
+        assert_eq!(hz, detuned_hz(7));
+        assert!(hz != detuned_hz(8));
+    }
+
+    /// Render the given number of frames.
+    fn render_frames<M>(instrument: &mut Instrument<M, ()>, frames: usize)
+        where M: ::Mode,
+    {
+        for _ in 0..frames {
+            for _ in instrument.frame_per_voice(SAMPLE_HZ) {}
+        }
+    }
+
+    #[test]
+    fn release_before_first_frame() {
+        let mut instrument = Instrument::new(Poly::default(), ()).release(100.0);
+        instrument.note_on(440.0, 1.0);
+        instrument.note_off(440.0);
+        let (frames, peak) = render(&mut instrument);
+        assert_eq!(frames, 4410);
+        assert_eq!(peak, 1.0);
+    }
+
+    #[test]
+    fn scheduled_release_before_first_frame() {
+        let mut instrument = Instrument::new(Poly::default(), ()).release(100.0);
+        instrument.note_on_at(5, 440.0, 1.0);
+        instrument.note_off_at(5, 440.0);
+        for _ in 0..5 {
+            instrument.frame_per_voice(SAMPLE_HZ).next();
+        }
+        let (frames, peak) = render(&mut instrument);
+        assert_eq!(frames, 4410);
+        assert_eq!(peak, 1.0);
+    }
+
+    #[test]
+    fn mono_release_before_first_frame() {
+        let mut instrument = Instrument::new(Mono::legato(), ()).adsr(0.0, 0.0, 0.5, 100.0);
+        instrument.note_on(440.0, 1.0);
+        render_frames(&mut instrument, 10);
+        instrument.note_off(440.0);
+        render(&mut instrument);
+        instrument.note_on(220.0, 1.0);
+        instrument.note_off(220.0);
+        let (frames, peak) = render(&mut instrument);
+        assert_eq!(frames, 4410);
+        assert_eq!(peak, 0.5);
+    }
+
+    #[test]
+    fn pressure_raises_velocity() {
+        let mut instrument = Instrument::new(Poly::default(), ())
+            .num_voices(2)
+            .pressure_to_velocity(0.5);
+        instrument.note_on_with_id(1, 440.0, 0.5);
+        instrument.note_on_with_id(2, 220.0, 0.5);
+        let vels = |instrument: &mut Instrument<Poly, ()>| -> Vec<f32> {
+            instrument.frame_per_voice(SAMPLE_HZ).filter_map(|frame| frame.map(|(vel, _)| vel))
+                .collect()
+        };
+        assert_eq!(vels(&mut instrument), vec![0.5, 0.5]);
+        instrument.set_note_pressure(2, 1.0);
+        assert_eq!(vels(&mut instrument), vec![0.5, 0.75]);
+        instrument.channel_pressure(0.5);
+        assert_eq!(vels(&mut instrument), vec![0.625, 0.625]);
+    }
+
+    /// The frequency of each sounding voice in the next frame.
+    fn frame_hz<M>(instrument: &mut Instrument<M, ()>) -> Vec<f32>
+        where M: ::Mode,
+    {
+        instrument.frame_per_voice(SAMPLE_HZ).filter_map(|frame| frame.map(|(_, hz)| hz)).collect()
+    }
+
+    fn assert_hz(hz: Vec<f32>, expected: &[f32]) {
+        assert_eq!(hz.len(), expected.len());
+        for (&hz, &expected) in hz.iter().zip(expected) {
+            assert!((hz - expected).abs() < 1e-2, "{} != {}", hz, expected);
+        }
+    }
+
+    #[test]
+    fn retune_sounding_poly_voice() {
+        let mut instrument = Instrument::new(Poly::default(), ()).num_voices(2);
+        instrument.handle_midi_bytes(&[0x90, 69, 100, 60, 100]);
+        assert_hz(frame_hz(&mut instrument), &[440.0, midi::key_to_hz(60)]);
+        instrument.retune_key(69, 432.0);
+        assert_hz(frame_hz(&mut instrument), &[432.0, midi::key_to_hz(60)]);
+    }
+
+    #[test]
+    fn retune_ignores_user_note_ids() {
+        let mut instrument = Instrument::new(Poly::default(), ()).num_voices(2);
+        instrument.note_on_with_id(69, 440.0, 1.0);
+        instrument.handle_midi_bytes(&[0x90, 69, 100]);
+        instrument.retune_key(69, 432.0);
+        assert_hz(frame_hz(&mut instrument), &[440.0, 432.0]);
+    }
+
+    #[test]
+    fn retune_stacked_mono_note() {
+        let mut instrument = Instrument::new(Mono::legato(), ());
+        instrument.handle_midi_bytes(&[0x90, 69, 100, 72, 100]);
+        assert_hz(frame_hz(&mut instrument), &[midi::key_to_hz(72)]);
+        // Retune the held key with an MTS single note tuning change.
+        instrument.handle_midi_bytes(&[0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 1, 69, 68, 0, 0, 0xF7]);
+        assert_hz(frame_hz(&mut instrument), &[midi::key_to_hz(72)]);
+        instrument.handle_midi_bytes(&[0x80, 72, 0]);
+        assert_hz(frame_hz(&mut instrument), &[midi::key_to_hz(68)]);
+    }
+}
@@ -6,14 +6,21 @@ extern crate pitch_calc as pitch;
 extern crate rand;
 extern crate time_calc as time;
 
-pub use instrument::{Frames, Instrument};
+pub use envelope::Envelope;
+pub use instrument::{Event, Frames, Instrument, VoiceFrame};
 pub use mode::Mode;
 pub use note_freq::{NoteFreq, NoteFreqGenerator};
 pub use voice::{NoteState, Voice};
 
+pub mod bend;
+pub mod envelope;
 mod instrument;
+pub mod midi;
 pub mod mode;
+pub mod mpe;
 pub mod note_freq;
+pub mod pan;
+pub mod tuning;
 pub mod unit;
 mod voice;
 
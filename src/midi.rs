@@ -0,0 +1,296 @@
+//! Conversion of MIDI 1.0 channel messages into `Instrument` events.
+
+use pitch;
+use unit::{NoteHz, NoteId, NoteVelocity};
+
+/// A MIDI channel in the range `0...15`.
+pub type Channel = u8;
+/// A MIDI key (note number) in the range `0...127`.
+pub type Key = u8;
+/// A 7-bit MIDI data value in the range `0...127`.
+pub type Value = u8;
+
+/// The controller number of the sustain (damper) pedal.
+pub const SUSTAIN_PEDAL: Value = 64;
+/// The controller number of the sostenuto pedal.
+pub const SOSTENUTO_PEDAL: Value = 66;
+/// The controller number of the "All Sound Off" channel mode message.
+pub const ALL_SOUND_OFF: Value = 120;
+/// The controller number of the "All Notes Off" channel mode message.
+pub const ALL_NOTES_OFF: Value = 123;
+
+/// The maximum length of the system exclusive messages collected by a `Parser`, excluding their
+/// leading `0xF0` and trailing `0xF7` bytes.
+///
+/// This is the length of the longest MIDI Tuning Standard message: a single note tuning change
+/// with a bank that retunes 127 keys, which is slightly longer than a bulk tuning dump.
+pub const MAX_SYSEX_LEN: usize = 7 + 4 * 127;
+
+/// A MIDI 1.0 channel message.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A key was released with the given release velocity.
+    NoteOff(Channel, Key, Value),
+    /// A key was pressed with the given velocity. A velocity of `0` is treated as a `NoteOff`.
+    NoteOn(Channel, Key, Value),
+    /// The pressure applied to a single held key.
+    PolyPressure(Channel, Key, Value),
+    /// A controller changed to the given value.
+    ControlChange(Channel, Value, Value),
+    /// The channel's program changed.
+    ProgramChange(Channel, Value),
+    /// The pressure applied to all held keys on the channel.
+    ChannelPressure(Channel, Value),
+    /// The channel's pitch bend changed to the given 14-bit value, where `8192` is centered.
+    PitchBend(Channel, u16),
+}
+
+/// Parses a stream of raw MIDI bytes into channel `Message`s.
+///
+/// Running status is supported: data bytes that arrive without a preceding status byte are
+/// interpreted using the status of the last channel message. System real-time bytes are ignored
+/// and may be interleaved anywhere in the stream. System exclusive messages are collected and may
+/// be retrieved with `take_sysex`, unless they are longer than `MAX_SYSEX_LEN`, while all other
+/// system messages are skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parser {
+    status: Option<u8>,
+    data: [Value; 2],
+    num_data: usize,
+    sysex: Option<Vec<u8>>,
+    complete_sysex: Option<Vec<u8>>,
+}
+
+
+/// The frequency of the given MIDI key in equal temperament where key `69` is A4 (440hz).
+pub fn key_to_hz(key: Key) -> NoteHz {
+    pitch::Step(key as pitch::calc::Step).hz()
+}
+
+/// The first of the `NoteId`s reserved for the notes played by MIDI messages, as produced by
+/// `note_id`.
+///
+/// `Instrument::note_on_with_id` ignores notes with a reserved id, so that a note started by the
+/// user is never mistaken for a MIDI note.
+pub const FIRST_NOTE_ID: NoteId = !0x7FF;
+
+/// The `NoteId` of the note played with the given key on the given channel.
+pub fn note_id(channel: Channel, key: Key) -> NoteId {
+    FIRST_NOTE_ID | (channel as NoteId & 0xF) << 7 | (key as NoteId & 0x7F)
+}
+
+/// Whether or not the given `NoteId` is one of those reserved for MIDI notes by `note_id`.
+pub fn is_note_id(note_id: NoteId) -> bool {
+    note_id >= FIRST_NOTE_ID
+}
+
+/// The key of the note with the given `NoteId`, as produced by `note_id`.
+pub fn key(note_id: NoteId) -> Key {
+    (note_id & 0x7F) as Key
+}
+
+/// The channel of the note with the given `NoteId`, as produced by `note_id`.
+pub fn channel(note_id: NoteId) -> Channel {
+    (note_id >> 7 & 0xF) as Channel
+}
+
+/// Convert the given MIDI velocity to a `NoteVelocity` in the range `0.0...1.0`.
+pub fn value_to_velocity(value: Value) -> NoteVelocity {
+    value as NoteVelocity / 127.0
+}
+
+/// Whether or not the given pedal controller value represents a pressed pedal.
+pub fn is_pedal_down(value: Value) -> bool {
+    value >= 64
+}
+
+
+impl Message {
+    /// The channel on which the message was sent.
+    pub fn channel(&self) -> Channel {
+        match *self {
+            Message::NoteOff(channel, _, _) |
+            Message::NoteOn(channel, _, _) |
+            Message::PolyPressure(channel, _, _) |
+            Message::ControlChange(channel, _, _) |
+            Message::ProgramChange(channel, _) |
+            Message::ChannelPressure(channel, _) |
+            Message::PitchBend(channel, _) => channel,
+        }
+    }
+}
+
+
+impl Parser {
+
+    /// Construct a new `Parser` with no running status.
+    pub fn new() -> Self {
+        Parser {
+            status: None,
+            data: [0; 2],
+            num_data: 0,
+            sysex: None,
+            complete_sysex: None,
+        }
+    }
+
+    /// Parse the next byte of the stream, returning a `Message` if the byte completes one.
+    pub fn parse(&mut self, byte: u8) -> Option<Message> {
+        match byte {
+            // System real-time messages may occur anywhere and do not affect running status.
+            0xF8..=0xFF => None,
+            // System common and system exclusive messages cancel running status.
+            0xF0..=0xF7 => {
+                self.status = None;
+                self.num_data = 0;
+                self.sysex = match byte {
+                    0xF0 => Some(vec![]),
+                    _ => {
+                        if byte == 0xF7 {
+                            self.complete_sysex = self.sysex.take();
+                        }
+                        None
+                    },
+                };
+                None
+            },
+            // A new channel message status.
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.num_data = 0;
+                self.sysex = None;
+                None
+            },
+            // A data byte of a system exclusive message.
+            _ if self.sysex.is_some() => {
+                match self.sysex {
+                    // Discard messages that are too long to be kept.
+                    Some(ref sysex) if sysex.len() >= MAX_SYSEX_LEN => self.sysex = None,
+                    Some(ref mut sysex) => sysex.push(byte),
+                    None => (),
+                }
+                None
+            },
+            // A data byte.
+            _ => {
+                let status = self.status?;
+                self.data[self.num_data] = byte;
+                self.num_data += 1;
+                let len = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                if self.num_data < len {
+                    return None;
+                }
+                self.num_data = 0;
+                let channel = status & 0x0F;
+                let [a, b] = self.data;
+                let message = match status & 0xF0 {
+                    0x80 => Message::NoteOff(channel, a, b),
+                    0x90 => Message::NoteOn(channel, a, b),
+                    0xA0 => Message::PolyPressure(channel, a, b),
+                    0xB0 => Message::ControlChange(channel, a, b),
+                    0xC0 => Message::ProgramChange(channel, a),
+                    0xD0 => Message::ChannelPressure(channel, a),
+                    _ => Message::PitchBend(channel, (b as u16) << 7 | a as u16),
+                };
+                Some(message)
+            },
+        }
+    }
+
+    /// Take the data of the most recently completed system exclusive message, excluding its
+    /// leading `0xF0` and trailing `0xF7` bytes.
+    ///
+    /// Returns `None` if no message has been completed since the last call.
+    pub fn take_sysex(&mut self) -> Option<Vec<u8>> {
+        self.complete_sysex.take()
+    }
+
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::new()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Message, Parser, MAX_SYSEX_LEN};
+
+    fn parse_all(parser: &mut Parser, bytes: &[u8]) -> Vec<Message> {
+        bytes.iter().filter_map(|&byte| parser.parse(byte)).collect()
+    }
+
+    #[test]
+    fn running_status() {
+        let mut parser = Parser::new();
+        let messages = parse_all(&mut parser, &[0x91, 60, 100, 64, 90, 60, 0]);
+        assert_eq!(messages, vec![
+            Message::NoteOn(1, 60, 100),
+            Message::NoteOn(1, 64, 90),
+            Message::NoteOn(1, 60, 0),
+        ]);
+    }
+
+    #[test]
+    fn data_without_status_is_ignored() {
+        let mut parser = Parser::new();
+        assert_eq!(parse_all(&mut parser, &[60, 100]), vec![]);
+    }
+
+    #[test]
+    fn real_time_interleaved() {
+        let mut parser = Parser::new();
+        let messages = parse_all(&mut parser, &[0xE0, 0xF8, 0x00, 0xFE, 0x40, 0xFA, 0x7F, 0x7F]);
+        assert_eq!(messages, vec![Message::PitchBend(0, 8192), Message::PitchBend(0, 16383)]);
+    }
+
+    #[test]
+    fn one_byte_messages() {
+        let mut parser = Parser::new();
+        let messages = parse_all(&mut parser, &[0xC3, 5, 6, 0xD2, 0xF8, 70, 80]);
+        assert_eq!(messages, vec![
+            Message::ProgramChange(3, 5),
+            Message::ProgramChange(3, 6),
+            Message::ChannelPressure(2, 70),
+            Message::ChannelPressure(2, 80),
+        ]);
+    }
+
+    #[test]
+    fn sysex_terminated() {
+        let mut parser = Parser::new();
+        let messages = parse_all(&mut parser, &[0x90, 60, 100, 0xF0, 1, 0xF8, 2, 3, 0xF7, 61, 100]);
+        // The sysex cancels running status, so the trailing data bytes are ignored.
+        assert_eq!(messages, vec![Message::NoteOn(0, 60, 100)]);
+        assert_eq!(parser.take_sysex(), Some(vec![1, 2, 3]));
+        assert_eq!(parser.take_sysex(), None);
+    }
+
+    #[test]
+    fn sysex_aborted_by_channel_status() {
+        let mut parser = Parser::new();
+        let messages = parse_all(&mut parser, &[0xF0, 1, 2, 0x80, 60, 0, 3, 0xF7]);
+        assert_eq!(messages, vec![Message::NoteOff(0, 60, 0)]);
+        assert_eq!(parser.take_sysex(), None);
+    }
+
+    #[test]
+    fn sysex_too_long_is_discarded() {
+        let mut parser = Parser::new();
+        let mut bytes = vec![0xF0];
+        bytes.extend(vec![1; MAX_SYSEX_LEN]);
+        bytes.push(0xF7);
+        assert_eq!(parse_all(&mut parser, &bytes), vec![]);
+        assert_eq!(parser.take_sysex(), Some(vec![1; MAX_SYSEX_LEN]));
+
+        bytes.insert(1, 1);
+        bytes.extend(&[0x90, 60, 100]);
+        assert_eq!(parse_all(&mut parser, &bytes), vec![Message::NoteOn(0, 60, 100)]);
+        assert_eq!(parser.take_sysex(), None);
+    }
+}
@@ -1,7 +1,8 @@
 use note_freq::NoteFreqGenerator;
+use rand;
 use std;
-use unit::{NoteHz, NoteVelocity};
-use voice::{self, NoteState, Voice};
+use unit::{NoteHz, NoteId, NoteVelocity, Pan};
+use voice::{self, Voice};
 
 
 /// The "mode" with which the `Instrument` will handle notes.
@@ -14,21 +15,52 @@ use voice::{self, NoteState, Voice};
 pub trait Mode {
 
     /// Handle a `note_on` event.
-    fn note_on<NFG>(&mut self,
-                    note_hz: NoteHz,
-                    note_velocity: NoteVelocity,
-                    detune: f32,
-                    note_freq_gen: &NFG,
-                    voices: &mut [Voice<NFG::NoteFreq>])
-        where NFG: NoteFreqGenerator;
+    ///
+    /// The note's `id`, if given, may be used by a later `note_off` to release exactly this note.
+    /// Otherwise notes already playing are matched against its hz using the `note_match` rule.
+    fn note_on<NFG, R>(&mut self,
+                       note_on: NoteOn,
+                       note_match: NoteMatch,
+                       detune: f32,
+                       note_freq_gen: &NFG,
+                       rng: &mut R,
+                       voices: &mut [Voice<NFG::NoteFreq>])
+        where NFG: NoteFreqGenerator,
+              R: rand::Rng;
 
     /// Handle a `note_off` event.
-    fn note_off<NFG>(&mut self,
-                     note_hz: NoteHz,
-                     detune: f32,
-                     note_freq_gen: &NFG,
-                     voices: &mut [Voice<NFG::NoteFreq>])
-        where NFG: NoteFreqGenerator;
+    ///
+    /// Notes that are held by the given `pedals` are marked as `sustained` rather than released.
+    fn note_off<NFG, R>(&mut self,
+                        note_off: NoteOff,
+                        pedals: Pedals,
+                        detune: f32,
+                        note_freq_gen: &NFG,
+                        rng: &mut R,
+                        voices: &mut [Voice<NFG::NoteFreq>])
+        where NFG: NoteFreqGenerator,
+              R: rand::Rng;
+
+    /// Handle a change in the state of the `pedals`.
+    ///
+    /// Releases each `sustained` note that is no longer held by the `pedals`.
+    fn pedals<NF>(&mut self, pedals: Pedals, voices: &mut [Voice<NF>]) {
+        for voice in voices.iter_mut() {
+            if voice.note.as_ref().is_some_and(|note| note.sustained) {
+                release(voice, pedals);
+            }
+        }
+    }
+
+    /// Handle the retuning of held notes, where `retune` returns the new hz of the note with the
+    /// given id if it was retuned.
+    ///
+    /// The notes of the voices are retuned by the `Instrument`, so only notes held by the mode
+    /// itself need to be updated.
+    fn retune<F>(&mut self, _retune: F)
+        where F: Fn(Option<NoteId>) -> Option<NoteHz>,
+    {
+    }
 
     /// Handle a `stop` event.
     fn stop(&mut self) {}
@@ -37,8 +69,14 @@ pub trait Mode {
 
 
 /// Monophonic playback.
+///
+/// The stack holds the hz and optional `NoteId` of each note that is still held beneath the
+/// currently playing note, in the order in which they were stacked.
+///
+/// Every voice plays the current note, spread according to the `Unison`. The `Priority` decides
+/// which of the held notes is played.
 #[derive(Clone, Debug, PartialEq)]
-pub struct Mono(pub MonoKind, pub Vec<NoteHz>);
+pub struct Mono(pub MonoKind, pub Vec<(NoteHz, Option<NoteId>)>, pub Unison, pub Priority);
 
 /// The state of monophony.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -47,14 +85,68 @@ pub enum MonoKind {
     Retrigger,
     /// If a note is already playing, new notes will not reset the voice's playheads.
     /// A stack of notes is kept - if a NoteOff occurs on the current note, it is replaced with the
-    /// note at the top of the stack if there is one. The stacked notes are reset if the voice
-    /// becomes inactive.
+    /// stacked note chosen by the `Priority` if there is one. The stacked notes are reset if the
+    /// voice becomes inactive.
     Legato,
 }
 
+/// Decides which of the held notes is played by `Mono`, both when a new note arrives and when the
+/// playing note is released.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Priority {
+    /// The most recently played note sounds.
+    #[default]
+    Last,
+    /// The lowest held note sounds.
+    Low,
+    /// The highest held note sounds.
+    High,
+    /// The earliest played note sounds until it is released.
+    First,
+}
+
+/// Spreads the voices playing a `Mono` note evenly across a range of detune and around the note's
+/// stereo position.
+///
+/// The first voice is placed at the lowest detune and leftmost position and the last voice at the
+/// highest detune and rightmost position.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Unison {
+    /// The width of the detune range in cents, centered around the note.
+    pub cents: f32,
+    /// The width of the stereo spread, from `0.0` (all voices centered) to `1.0` (from hard left
+    /// to hard right).
+    pub pan: f32,
+}
+
 /// Polyphonic playback.
+///
+/// New notes are played on a free voice if there is one, otherwise a voice is stolen according to
+/// the `Steal` policy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Poly(pub Steal);
+
+/// The policy with which `Poly` chooses a voice to steal when a new note arrives and no voices are
+/// free.
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Poly;
+pub enum Steal {
+    /// Steal the voice playing the note that began the longest ago.
+    Oldest,
+    /// Steal the voice with the lowest current amplitude.
+    Quietest,
+    /// Steal the oldest voice whose note is fading out after having been released, followed by
+    /// the oldest whose key has been released but is sustained by a pedal, followed by the oldest.
+    Released,
+    /// Steal the voice playing the lowest pitch.
+    Lowest,
+    /// Steal the voice playing the highest pitch.
+    Highest,
+    /// If a voice is already playing the same note, it is reused even if there are free voices.
+    /// Otherwise a voice is stolen as for `Released`.
+    SameNote,
+    /// Never steal a voice. New notes are dropped while all voices are busy.
+    Never,
+}
 
 /// The mode in which the Synth will handle notes.
 #[derive(Clone, Debug, PartialEq)]
@@ -66,31 +158,237 @@ pub enum Dynamic {
 }
 
 
-/// Does the given `hz` match the `target_hz`?
-pub fn does_hz_match(hz: NoteHz, target_hz: NoteHz) -> bool {
-    const HZ_VARIANCE: NoteHz = 0.25;
-    let (min_hz, max_hz) = (target_hz - HZ_VARIANCE, target_hz + HZ_VARIANCE);
-    hz > min_hz && hz < max_hz
+/// The state of the pedals that may hold notes after their keys have been released.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Pedals {
+    /// Whether or not the sustain pedal is down, holding every note whose key is released.
+    pub sustain: bool,
+    /// Whether or not the sostenuto pedal is down, holding only the notes that were `latched` by
+    /// the pedal being pressed while their keys were held.
+    pub sostenuto: bool,
+}
+
+/// A note to be played by a `note_on` event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NoteOn {
+    /// The frequency of the note.
+    pub hz: NoteHz,
+    /// The unique identifier of the note, if any.
+    pub id: Option<NoteId>,
+    /// The velocity of the note.
+    pub vel: NoteVelocity,
+    /// The stereo position of the note from `-1.0` (left) to `1.0` (right).
+    pub pan: Pan,
+    /// The number of notes started by the `Instrument` before this one.
+    ///
+    /// This is used in place of a timestamp to determine the age of a note, so that note
+    /// ordering depends only on the sequence of events.
+    pub order: u64,
+}
+
+/// Identifies the note that a `note_off` event should release.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoteOff {
+    /// Release the note whose `note_on` hz matches the given hz according to the `NoteMatch`.
+    Hz(NoteHz, NoteMatch),
+    /// Release the note that was started with the given `NoteId`.
+    Id(NoteId),
+}
+
+/// The rule by which a `NoteOff::Hz` is matched against the hz of each held note.
+///
+/// The default matches notes within one cent of the target hz.
+#[derive(Copy, Clone, Debug)]
+pub enum NoteMatch {
+    /// Match notes whose hz is within the given number of cents of the target hz.
+    ///
+    /// As the tolerance is relative, it is equally strict in every register.
+    Cents(f32),
+    /// Match notes for which the given function, called with the hz of the note and the target
+    /// hz, returns `true`.
+    Custom(fn(NoteHz, NoteHz) -> bool),
 }
 
-/// Is the given `voice` currently playing a note that matches the `target_hz`?
-fn does_voice_match<NF>(voice: &Voice<NF>, target_hz: NoteHz) -> bool {
+
+/// Does the note with the given `hz` and `id` match the note identified by `note_off`?
+pub fn does_note_match(hz: NoteHz, id: Option<NoteId>, note_off: NoteOff) -> bool {
+    match note_off {
+        NoteOff::Hz(target_hz, note_match) => note_match.does_hz_match(hz, target_hz),
+        NoteOff::Id(target_id) => id == Some(target_id),
+    }
+}
+
+/// Is the given `voice` currently playing a held note that matches the `note_off`?
+///
+/// Notes that are only `sustained` by a pedal have already had their key released and never match.
+fn does_voice_match<NF>(voice: &Voice<NF>, note_off: NoteOff) -> bool {
     match voice.note {
-        Some(voice::Note { state: NoteState::Playing, hz, .. }) =>
-            does_hz_match(hz, target_hz),
+        Some(voice::Note { state, hz, id, sustained: false, .. }) if state.is_playing() =>
+            does_note_match(hz, id, note_off),
         _ => false,
     }
 }
 
+/// Release the `voice`'s note, unless it is held by the `pedals` in which case it is marked as
+/// `sustained` instead.
+fn release<NF>(voice: &mut Voice<NF>, pedals: Pedals) {
+    if let Some(ref mut note) = voice.note {
+        if pedals.holds(note) {
+            note.sustained = true;
+            return;
+        }
+    }
+    voice.note_off();
+}
+
+/// Select the voice to steal for a new note according to the `steal` policy.
+fn voice_to_steal<NF>(steal: Steal, voices: &mut [Voice<NF>]) -> Option<&mut Voice<NF>> {
+    fn cmp(a: f32, b: f32) -> std::cmp::Ordering {
+        a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+    }
+
+    let idx = {
+        let notes = voices.iter().enumerate()
+            .filter_map(|(idx, voice)| voice.note.as_ref().map(|note| (idx, note)));
+        let maybe_note = match steal {
+            Steal::Never => None,
+            Steal::Oldest => notes.min_by_key(|&(_, note)| note.order),
+            Steal::Quietest => notes.min_by(|&(_, a), &(_, b)| cmp(a.vel * a.amp, b.vel * b.amp)),
+            Steal::Released | Steal::SameNote => notes.min_by_key(|&(_, note)| {
+                (note.state.is_playing(), !note.sustained, note.order)
+            }),
+            Steal::Lowest => notes.min_by(|&(_, a), &(_, b)| cmp(a.hz, b.hz)),
+            Steal::Highest => notes.max_by(|&(_, a), &(_, b)| cmp(a.hz, b.hz)),
+        };
+        maybe_note.map(|(idx, _)| idx)
+    };
+
+    idx.map(move |idx| &mut voices[idx])
+}
+
+
+impl NoteOn {
+    /// The `NoteOff` that identifies this note, by its `id` if it has one or otherwise its `hz`
+    /// as matched by the given `NoteMatch`.
+    pub fn note_off(&self, note_match: NoteMatch) -> NoteOff {
+        match self.id {
+            Some(id) => NoteOff::Id(id),
+            None => NoteOff::Hz(self.hz, note_match),
+        }
+    }
+}
+
+
+impl NoteMatch {
+    /// Does the given `hz` match the `target_hz`?
+    pub fn does_hz_match(&self, hz: NoteHz, target_hz: NoteHz) -> bool {
+        match *self {
+            NoteMatch::Cents(cents) => (1200.0 * (hz / target_hz).log2()).abs() <= cents,
+            NoteMatch::Custom(does_match) => does_match(hz, target_hz),
+        }
+    }
+}
+
+impl PartialEq for NoteMatch {
+    /// `Custom` rules are equal only if they call the same function.
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (NoteMatch::Cents(a), NoteMatch::Cents(b)) => a == b,
+            (NoteMatch::Custom(a), NoteMatch::Custom(b)) => a as usize == b as usize,
+            _ => false,
+        }
+    }
+}
+
+impl Default for NoteMatch {
+    fn default() -> Self {
+        NoteMatch::Cents(1.0)
+    }
+}
+
+
+impl Pedals {
+    /// Whether or not the pedals hold the given `note` once its key has been released.
+    pub fn holds<NF>(&self, note: &voice::Note<NF>) -> bool {
+        self.sustain || (self.sostenuto && note.latched)
+    }
+}
+
 
 impl Mono {
     /// Construct a default Retrigger mono mode.
     pub fn retrigger() -> Mono {
-        Mono(MonoKind::Retrigger, Vec::with_capacity(16))
+        Mono(MonoKind::Retrigger, Vec::with_capacity(16), Unison::default(), Priority::default())
     }
     /// construct a default Legato mono mode.
     pub fn legato() -> Mono {
-        Mono(MonoKind::Legato, Vec::with_capacity(16))
+        Mono(MonoKind::Legato, Vec::with_capacity(16), Unison::default(), Priority::default())
+    }
+    /// Spread the voices according to the given `Unison`.
+    pub fn unison(self, unison: Unison) -> Mono {
+        let Mono(kind, notes, _, priority) = self;
+        Mono(kind, notes, unison, priority)
+    }
+    /// Choose the played note among those held according to the given `Priority`.
+    pub fn priority(self, priority: Priority) -> Mono {
+        let Mono(kind, notes, unison, _) = self;
+        Mono(kind, notes, unison, priority)
+    }
+}
+
+
+impl Unison {
+    /// Construct a new `Unison` spread.
+    pub fn new(cents: f32, pan: f32) -> Self {
+        Unison { cents, pan }
+    }
+
+    /// The detune in cents and the pan of the voice at `idx` among `num_voices`.
+    pub fn spread(&self, idx: usize, num_voices: usize) -> (f32, Pan) {
+        if num_voices < 2 {
+            return (0.0, 0.0);
+        }
+        let pos = idx as f32 / (num_voices - 1) as f32 * 2.0 - 1.0;
+        (pos * self.cents / 2.0, pos * self.pan)
+    }
+
+    /// Spread the notes of the given `voices`.
+    fn apply<NF>(&self, voices: &mut [Voice<NF>]) {
+        let num_voices = voices.len();
+        for (idx, voice) in voices.iter_mut().enumerate() {
+            let (cents, pan) = self.spread(idx, num_voices);
+            voice.spread(cents, pan);
+        }
+    }
+}
+
+
+impl Priority {
+    /// Whether or not a new note with the given `hz` should replace the note playing at
+    /// `playing_hz`.
+    fn replaces(&self, hz: NoteHz, playing_hz: NoteHz) -> bool {
+        match *self {
+            Priority::Last => true,
+            Priority::Low => hz < playing_hz,
+            Priority::High => hz > playing_hz,
+            Priority::First => false,
+        }
+    }
+
+    /// The index of the stacked note that should be played once the playing note is released.
+    ///
+    /// Among notes of equal hz, the most recently stacked is chosen.
+    fn next(&self, notes: &[(NoteHz, Option<NoteId>)]) -> Option<usize> {
+        let last = notes.len().checked_sub(1);
+        match *self {
+            Priority::Last => last,
+            Priority::First => last.map(|_| 0),
+            Priority::Low | Priority::High => last.map(|last| {
+                (0..last).rev().fold(last, |best, idx| {
+                    if self.replaces(notes[idx].0, notes[best].0) { idx } else { best }
+                })
+            }),
+        }
     }
 }
 
@@ -106,7 +404,14 @@ impl Dynamic {
     }
     /// Construct a default Poly mode.
     pub fn poly() -> Dynamic {
-        Dynamic::Poly(Poly)
+        Dynamic::Poly(Poly::default())
+    }
+}
+
+
+impl Default for Poly {
+    fn default() -> Self {
+        Poly(Steal::Released)
     }
 }
 
@@ -114,92 +419,119 @@ impl Dynamic {
 impl Mode for Mono {
 
     /// Handle a note_on event.
-    fn note_on<NFG>(&mut self,
-                    note_hz: NoteHz,
-                    note_vel: NoteVelocity,
-                    detune: f32,
-                    note_freq_gen: &NFG,
-                    voices: &mut [Voice<NFG::NoteFreq>])
+    fn note_on<NFG, R>(&mut self,
+                       note_on: NoteOn,
+                       note_match: NoteMatch,
+                       detune: f32,
+                       note_freq_gen: &NFG,
+                       rng: &mut R,
+                       voices: &mut [Voice<NFG::NoteFreq>])
         where NFG: NoteFreqGenerator,
+              R: rand::Rng,
     {
         // To ensure that we don't double-stack notes when multiple `note_on`s are given for the
         // same note, we first release the note if it exists.
-        self.note_off(note_hz, detune, note_freq_gen, voices);
+        let note_off = note_on.note_off(note_match);
+        self.note_off(note_off, Pedals::default(), detune, note_freq_gen, rng, voices);
 
-        let Mono(kind, ref mut notes) = *self;
+        let Mono(kind, ref mut notes, unison, priority) = *self;
 
-        // If a note was already playing, move it onto the stack
-        if let Some(voice::Note { state: NoteState::Playing, hz, .. }) = voices[0].note {
-            notes.push(hz);
+        // If a note was already playing, move it onto the stack unless its key has already been
+        // released and it is only being sustained by a pedal.
+        let maybe_playing = match voices[0].note {
+            Some(voice::Note { state, hz, id, sustained, .. }) if state.is_playing() =>
+                Some((hz, id, sustained)),
+            _ => None,
+        };
+        let retrigger = if let Some((hz, id, sustained)) = maybe_playing {
+            // If the held note takes priority over the new note, stack the new note instead.
+            if !sustained && !priority.replaces(note_on.hz, hz) {
+                notes.push((note_on.hz, note_on.id));
+                return;
+            }
 
-            // If in Retrigger mode, reset the playheads.
-            if let MonoKind::Retrigger = kind {
-                for voice in voices.iter_mut() {
-                    voice.reset_playhead();
-                }
+            if !sustained {
+                notes.push((hz, id));
             }
+
+            // Only restart the envelope in Retrigger mode.
+            kind == MonoKind::Retrigger
         }
-        // Otherwise if there were no notes currently playing, reset the playheads anyway.
+        // Otherwise if there were no notes currently playing, restart the envelope anyway.
         else {
             notes.clear();
-            for voice in voices.iter_mut() {
-                voice.reset_playhead();
-            }
-        }
+            true
+        };
 
-        // Generate a unique NoteFreq and trigger note_on for each voice.
+        // Generate a unique NoteFreq and trigger note_on for each voice, fading out of any
+        // releasing note when the envelope restarts.
         for voice in voices.iter_mut() {
-            let freq = note_freq_gen.generate(note_hz, detune, Some(voice));
-            voice.note_on(note_hz, freq, note_vel);
+            let freq = note_freq_gen.generate(note_on.hz, detune, rng, Some(voice));
+            if retrigger {
+                voice.steal(note_on, freq);
+            } else {
+                voice.note_on(note_on, freq);
+            }
         }
+        unison.apply(voices);
     }
 
     /// Handle a note_off event.
-    fn note_off<NFG>(&mut self,
-                     note_hz: NoteHz,
-                     detune: f32,
-                     note_freq_gen: &NFG,
-                     voices: &mut [Voice<NFG::NoteFreq>])
+    fn note_off<NFG, R>(&mut self,
+                        note_off: NoteOff,
+                        pedals: Pedals,
+                        detune: f32,
+                        note_freq_gen: &NFG,
+                        rng: &mut R,
+                        voices: &mut [Voice<NFG::NoteFreq>])
         where NFG: NoteFreqGenerator,
+              R: rand::Rng,
     {
-        let Mono(kind, ref mut notes) = *self;
-
-        if does_voice_match(&mut voices[0], note_hz) {
-            if let Some(voice::Note { vel, .. }) = voices[0].note {
-                // If there's a note still on the stack, fall back to it.
-                if let Some(old_hz) = notes.pop() {
-
-                    if let MonoKind::Retrigger = kind {
-                        for voice in voices.iter_mut() {
-                            voice.reset_playhead();
-                        }
-                    }
-
-                    // Play the popped stack note on all voices.
+        let Mono(kind, ref mut notes, unison, priority) = *self;
+
+        if does_voice_match(&voices[0], note_off) {
+            if let Some(voice::Note { vel, pan, order, .. }) = voices[0].note {
+                // If there's a note still on the stack, fall back to the one with priority.
+                if let Some((hz, id)) = priority.next(notes).map(|idx| notes.remove(idx)) {
+                    // Play the popped stack note on all voices, restarting the envelope in
+                    // Retrigger mode.
+                    let note_on = NoteOn { hz, id, vel, pan, order };
                     for voice in voices.iter_mut() {
-                        let freq = note_freq_gen.generate(old_hz, detune, Some(voice));
-                        voice.note_on(old_hz, freq, vel);
+                        let freq = note_freq_gen.generate(hz, detune, rng, Some(voice));
+                        match kind {
+                            MonoKind::Retrigger => voice.steal(note_on, freq),
+                            MonoKind::Legato => voice.note_on(note_on, freq),
+                        }
                     }
+                    unison.apply(voices);
                     return;
                 }
             }
             for voice in voices.iter_mut() {
-                voice.note_off();
+                release(voice, pedals);
             }
         } else {
             // If any notes in the note stack match the given note_off, remove them.
-            for i in (0..notes.len()).rev() {
-                if does_hz_match(notes[i], note_hz) {
-                    notes.remove(i);
-                }
-            }
+            notes.retain(|&(hz, id)| !does_note_match(hz, id, note_off));
         }
 
     }
 
+    /// Retune the notes in the note stack.
+    fn retune<F>(&mut self, retune: F)
+        where F: Fn(Option<NoteId>) -> Option<NoteHz>,
+    {
+        let Mono(_, ref mut notes, _, _) = *self;
+        for &mut (ref mut hz, id) in notes.iter_mut() {
+            if let Some(new_hz) = retune(id) {
+                *hz = new_hz;
+            }
+        }
+    }
+
     /// Handle a stop event.
     fn stop(&mut self) {
-        let Mono(_, ref mut notes) = *self;
+        let Mono(_, ref mut notes, _, _) = *self;
         notes.clear();
     }
 
@@ -208,74 +540,78 @@ impl Mode for Mono {
 
 impl Mode for Poly {
 
-    fn note_on<NFG>(&mut self,
-                    note_hz: NoteHz,
-                    note_vel: NoteVelocity,
-                    detune: f32,
-                    note_freq_gen: &NFG,
-                    voices: &mut [Voice<NFG::NoteFreq>])
+    fn note_on<NFG, R>(&mut self,
+                       note_on: NoteOn,
+                       note_match: NoteMatch,
+                       detune: f32,
+                       note_freq_gen: &NFG,
+                       rng: &mut R,
+                       voices: &mut [Voice<NFG::NoteFreq>])
         where NFG: NoteFreqGenerator,
+              R: rand::Rng,
     {
         // Construct the new CurrentFreq for the new note.
         let freq = {
-            // First, determine the current hz of the last note played if there is one.
-            let mut active = voices.iter().filter(|voice| voice.note.is_some());
-
-            // Find the most recent voice.
-            let maybe_newest_voice = active.next().map(|voice| {
-                active.fold(voice, |newest, voice| {
-                    if voice.playhead < newest.playhead { voice }
-                    else { newest }
+            // Find the most recent voice, preferring those whose keys are still held so that the
+            // new note may glide from a note that it overlaps.
+            let maybe_newest_voice = voices.iter()
+                .filter_map(|voice| voice.note.as_ref().map(|note| (voice, note)))
+                .max_by_key(|&(_, note)| {
+                    let is_held = note.state.is_playing() && !note.sustained;
+                    (is_held, note.order)
                 })
-            });
+                .map(|(voice, _)| voice);
 
-            note_freq_gen.generate(note_hz, detune, maybe_newest_voice)
+            note_freq_gen.generate(note_on.hz, detune, rng, maybe_newest_voice)
         };
 
-        // Find the right voice to play the note.
-        let mut oldest = None;
-        let mut oldest_note_on = std::time::Instant::now();
-        for voice in voices.iter_mut() {
-            if voice.note.is_none() {
-                voice.reset_playhead();
-                voice.note_on(note_hz, freq, note_vel);
+        let Poly(steal) = *self;
+
+        // Reuse the voice already playing the same note if the policy requires it.
+        if let Steal::SameNote = steal {
+            let note_off = note_on.note_off(note_match);
+            let maybe_voice = voices.iter_mut().find(|voice| match voice.note {
+                Some(ref note) => does_note_match(note.hz, note.id, note_off),
+                None => false,
+            });
+            if let Some(voice) = maybe_voice {
+                voice.steal(note_on, freq);
                 return;
             }
-            let time_of_note_on = voice.note.as_ref().unwrap().time_of_note_on;
-            if time_of_note_on < oldest_note_on {
-                oldest_note_on = time_of_note_on;
-                oldest = Some(voice);
-            }
         }
-        if let Some(voice) = oldest {
+
+        // Play the note on a free voice if there is one.
+        if let Some(voice) = voices.iter_mut().find(|voice| voice.note.is_none()) {
             voice.reset_playhead();
-            voice.note_on(note_hz, freq, note_vel);
+            voice.note_on(note_on, freq);
+            return;
+        }
+
+        // Otherwise steal a voice according to the policy.
+        if let Some(voice) = voice_to_steal(steal, voices) {
+            voice.steal(note_on, freq);
         }
 
     }
 
-    fn note_off<NFG>(&mut self,
-                     note_hz: NoteHz,
-                     _detune: f32,
-                     _note_freq_gen: &NFG,
-                     voices: &mut [Voice<NFG::NoteFreq>])
+    fn note_off<NFG, R>(&mut self,
+                        note_off: NoteOff,
+                        pedals: Pedals,
+                        _detune: f32,
+                        _note_freq_gen: &NFG,
+                        _rng: &mut R,
+                        voices: &mut [Voice<NFG::NoteFreq>])
         where NFG: NoteFreqGenerator,
+              R: rand::Rng,
     {
 
-        let maybe_voice = voices.iter_mut().fold(None, |maybe_current_match, voice| {
-            if does_voice_match(voice, note_hz) {
-                match maybe_current_match {
-                    None => return Some(voice),
-                    Some(ref current_match) => if voice.playhead >= current_match.playhead {
-                        return Some(voice)
-                    },
-                }
-            }
-            maybe_current_match
-        });
+        // If several held notes match, release the oldest.
+        let maybe_voice = voices.iter_mut()
+            .filter(|voice| does_voice_match(voice, note_off))
+            .min_by_key(|voice| voice.note.as_ref().map(|note| note.order));
 
         if let Some(voice) = maybe_voice {
-            voice.note_off();
+            release(voice, pedals);
         }
     }
 
@@ -285,30 +621,55 @@ impl Mode for Poly {
 impl Mode for Dynamic {
 
     /// Handle a note_on event.
-    fn note_on<NFG>(&mut self,
-                    note_hz: NoteHz,
-                    note_vel: NoteVelocity,
-                    detune: f32,
-                    note_freq_gen: &NFG,
-                    voices: &mut [Voice<NFG::NoteFreq>]) where NFG: NoteFreqGenerator {
+    fn note_on<NFG, R>(&mut self,
+                       note_on: NoteOn,
+                       note_match: NoteMatch,
+                       detune: f32,
+                       note_freq_gen: &NFG,
+                       rng: &mut R,
+                       voices: &mut [Voice<NFG::NoteFreq>])
+        where NFG: NoteFreqGenerator,
+              R: rand::Rng,
+    {
         match *self {
             Dynamic::Mono(ref mut mono) =>
-                mono.note_on(note_hz, note_vel, detune, note_freq_gen, voices),
+                mono.note_on(note_on, note_match, detune, note_freq_gen, rng, voices),
             Dynamic::Poly(ref mut poly) =>
-                poly.note_on(note_hz, note_vel, detune, note_freq_gen, voices),
+                poly.note_on(note_on, note_match, detune, note_freq_gen, rng, voices),
         }
     }
 
-    fn note_off<NFG>(&mut self,
-                     note_hz: NoteHz,
-                     detune: f32,
-                     note_freq_gen: &NFG,
-                     voices: &mut [Voice<NFG::NoteFreq>]) where NFG: NoteFreqGenerator {
+    fn note_off<NFG, R>(&mut self,
+                        note_off: NoteOff,
+                        pedals: Pedals,
+                        detune: f32,
+                        note_freq_gen: &NFG,
+                        rng: &mut R,
+                        voices: &mut [Voice<NFG::NoteFreq>])
+        where NFG: NoteFreqGenerator,
+              R: rand::Rng,
+    {
         match *self {
             Dynamic::Mono(ref mut mono) =>
-                mono.note_off(note_hz, detune, note_freq_gen, voices),
+                mono.note_off(note_off, pedals, detune, note_freq_gen, rng, voices),
             Dynamic::Poly(ref mut poly) =>
-                poly.note_off(note_hz, detune, note_freq_gen, voices),
+                poly.note_off(note_off, pedals, detune, note_freq_gen, rng, voices),
+        }
+    }
+
+    fn pedals<NF>(&mut self, pedals: Pedals, voices: &mut [Voice<NF>]) {
+        match *self {
+            Dynamic::Mono(ref mut mono) => mono.pedals(pedals, voices),
+            Dynamic::Poly(ref mut poly) => poly.pedals(pedals, voices),
+        }
+    }
+
+    fn retune<F>(&mut self, retune: F)
+        where F: Fn(Option<NoteId>) -> Option<NoteHz>,
+    {
+        match *self {
+            Dynamic::Mono(ref mut mono) => mono.retune(retune),
+            Dynamic::Poly(ref mut poly) => poly.retune(retune),
         }
     }
 
@@ -321,3 +682,296 @@ impl Mode for Dynamic {
 
 }
 
+
+#[cfg(test)]
+mod test {
+    use Instrument;
+    use note_freq::Portamento;
+    use pitch;
+    use super::{Mono, NoteMatch, Poly, Priority, Steal, Unison};
+    use unit::NoteHz;
+
+    /// The hz of the note on each voice, or `None` if the voice is free.
+    fn voice_hz<M>(instrument: &Instrument<M, ()>) -> Vec<Option<NoteHz>> {
+        instrument.voices.iter().map(|voice| voice.note.as_ref().map(|note| note.hz)).collect()
+    }
+
+    /// Render a single frame so that the amplitude of each note is updated.
+    fn render_frame<M>(instrument: &mut Instrument<M, ()>)
+        where M: ::Mode,
+    {
+        for _ in instrument.frame_per_voice(44_100.0) {}
+    }
+
+    fn poly(steal: Steal) -> Instrument<Poly, ()> {
+        Instrument::new(Poly(steal), ()).num_voices(2).release(100.0)
+    }
+
+    #[test]
+    fn note_off_releases_oldest_duplicate() {
+        let mut instrument = poly(Steal::Oldest);
+        instrument.note_on_at(0, 100.0, 0.5);
+        instrument.note_on_at(0, 100.0, 1.0);
+        instrument.note_off_at(0, 100.0);
+        render_frame(&mut instrument);
+        let held: Vec<_> = instrument.voices.iter()
+            .filter_map(|voice| voice.note.as_ref())
+            .filter(|note| note.state.is_playing())
+            .map(|note| note.vel)
+            .collect();
+        assert_eq!(held, vec![1.0]);
+    }
+
+    #[test]
+    fn glide_from_newest_note_of_the_same_frame() {
+        let mut instrument = Instrument::new(Poly::default(), Portamento::legato(100))
+            .num_voices(3);
+        instrument.note_on_at(0, 100.0, 1.0);
+        instrument.note_on_at(0, 200.0, 1.0);
+        instrument.note_on_at(1, 300.0, 1.0);
+        for _ in 0..2 {
+            for _ in instrument.frame_per_voice(44_100.0) {}
+        }
+        // The note at 200hz is itself gliding up from 100hz, while the note at 100hz is not.
+        let start_mel = instrument.voices[2].note.as_ref().map_or(0.0, |note| note.freq.start_mel);
+        assert!(start_mel > pitch::Hz(100.0).mel());
+    }
+
+    #[test]
+    fn steal_never() {
+        let mut instrument = poly(Steal::Never);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_on(300.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(100.0), Some(200.0)]);
+    }
+
+    #[test]
+    fn steal_oldest() {
+        let mut instrument = poly(Steal::Oldest);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_on(300.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(300.0), Some(200.0)]);
+    }
+
+    #[test]
+    fn steal_quietest() {
+        let mut instrument = poly(Steal::Quietest);
+        instrument.note_on(100.0, 0.9);
+        instrument.note_on(200.0, 0.2);
+        render_frame(&mut instrument);
+        instrument.note_on(300.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(100.0), Some(300.0)]);
+    }
+
+    #[test]
+    fn steal_lowest_and_highest() {
+        let mut instrument = poly(Steal::Lowest);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(300.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(200.0), Some(300.0)]);
+
+        let mut instrument = poly(Steal::Highest);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(300.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(300.0), Some(100.0)]);
+    }
+
+    #[test]
+    fn steal_same_note_while_voices_are_free() {
+        let mut instrument = poly(Steal::SameNote);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(100.0, 0.5);
+        assert_eq!(voice_hz(&instrument), vec![Some(100.0), None]);
+        assert_eq!(instrument.voices[0].note.as_ref().map(|note| note.vel), Some(0.5));
+    }
+
+    #[test]
+    fn steal_released_prefers_fading_then_sustained() {
+        let mut instrument = poly(Steal::Released).num_voices(3);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_off(200.0);
+        instrument.sustain_pedal(true);
+        instrument.note_on(300.0, 1.0);
+        instrument.note_off(300.0);
+        // The fading note is stolen first.
+        instrument.note_on(400.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(100.0), Some(400.0), Some(300.0)]);
+        // Followed by the note held only by the sustain pedal.
+        instrument.note_on(500.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(100.0), Some(400.0), Some(500.0)]);
+        // Followed by the oldest held note.
+        instrument.note_on(600.0, 1.0);
+        assert_eq!(voice_hz(&instrument), vec![Some(600.0), Some(400.0), Some(500.0)]);
+    }
+
+    /// The hz of each note whose key is held or that is held by a pedal.
+    fn held_hz<M>(instrument: &Instrument<M, ()>) -> Vec<NoteHz> {
+        instrument.voices.iter()
+            .filter_map(|voice| voice.note.as_ref())
+            .filter(|note| note.state.is_playing())
+            .map(|note| note.hz)
+            .collect()
+    }
+
+    #[test]
+    fn sostenuto_holds_only_latched_notes() {
+        let mut instrument = poly(Steal::Never);
+        instrument.note_on(100.0, 1.0);
+        instrument.sostenuto_pedal(true);
+        instrument.note_on(200.0, 1.0);
+        instrument.note_off(100.0);
+        instrument.note_off(200.0);
+        // The note held when the pedal went down keeps sounding, while the later note releases.
+        assert_eq!(held_hz(&instrument), vec![100.0]);
+        instrument.sostenuto_pedal(false);
+        assert_eq!(held_hz(&instrument), vec![]);
+    }
+
+    fn mono(priority: Priority) -> Instrument<Mono, ()> {
+        Instrument::new(Mono::legato().priority(priority), ())
+    }
+
+    /// Play the given notes in order, returning the hz of the sounding note after each.
+    fn play(instrument: &mut Instrument<Mono, ()>, notes: &[NoteHz]) -> Vec<Option<NoteHz>> {
+        notes.iter().map(|&hz| {
+            instrument.note_on(hz, 1.0);
+            voice_hz(instrument)[0]
+        }).collect()
+    }
+
+    /// Release the sounding note until none remain, returning the hz of the note that falls
+    /// back after each release.
+    fn release_all(instrument: &mut Instrument<Mono, ()>) -> Vec<Option<NoteHz>> {
+        let mut fallbacks = vec![];
+        while let Some(hz) = voice_hz(instrument)[0] {
+            instrument.note_off(hz);
+            render_frame(instrument);
+            fallbacks.push(voice_hz(instrument)[0]);
+        }
+        fallbacks
+    }
+
+    #[test]
+    fn priority_last() {
+        let mut instrument = mono(Priority::Last);
+        let played = play(&mut instrument, &[200.0, 100.0, 300.0]);
+        assert_eq!(played, vec![Some(200.0), Some(100.0), Some(300.0)]);
+        assert_eq!(release_all(&mut instrument), vec![Some(100.0), Some(200.0), None]);
+    }
+
+    #[test]
+    fn priority_low() {
+        let mut instrument = mono(Priority::Low);
+        let played = play(&mut instrument, &[200.0, 100.0, 300.0, 150.0]);
+        assert_eq!(played, vec![Some(200.0), Some(100.0), Some(100.0), Some(100.0)]);
+        let fallbacks = release_all(&mut instrument);
+        assert_eq!(fallbacks, vec![Some(150.0), Some(200.0), Some(300.0), None]);
+    }
+
+    #[test]
+    fn priority_high() {
+        let mut instrument = mono(Priority::High);
+        let played = play(&mut instrument, &[200.0, 100.0, 300.0, 150.0]);
+        assert_eq!(played, vec![Some(200.0), Some(200.0), Some(300.0), Some(300.0)]);
+        let fallbacks = release_all(&mut instrument);
+        assert_eq!(fallbacks, vec![Some(200.0), Some(150.0), Some(100.0), None]);
+    }
+
+    #[test]
+    fn priority_first() {
+        let mut instrument = mono(Priority::First);
+        let played = play(&mut instrument, &[200.0, 100.0, 300.0]);
+        assert_eq!(played, vec![Some(200.0), Some(200.0), Some(200.0)]);
+        assert_eq!(release_all(&mut instrument), vec![Some(100.0), Some(300.0), None]);
+    }
+
+    #[test]
+    fn priority_releasing_a_stacked_note() {
+        let mut instrument = mono(Priority::Low);
+        play(&mut instrument, &[100.0, 200.0, 300.0]);
+        instrument.note_off(200.0);
+        assert_eq!(release_all(&mut instrument), vec![Some(300.0), None]);
+    }
+
+    /// Render the given number of frames, returning the velocity of the first voice in each.
+    fn render_vel(instrument: &mut Instrument<Mono, ()>, frames: usize) -> Vec<f32> {
+        (0..frames).map(|_| {
+            match instrument.frame_per_voice(44_100.0).next() {
+                Some(Some((vel, _))) => vel,
+                _ => 0.0,
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn retrigger_is_continuous() {
+        let mut instrument = Instrument::new(Mono::retrigger(), ()).adsr(10.0, 0.0, 1.0, 10.0);
+        instrument.note_on(440.0, 1.0);
+        let mut vels = render_vel(&mut instrument, 1000);
+        // Retrigger from a held note.
+        instrument.note_on(220.0, 1.0);
+        vels.extend(render_vel(&mut instrument, 1000));
+        // Retrigger by falling back to the stacked note.
+        instrument.note_off(220.0);
+        vels.extend(render_vel(&mut instrument, 1000));
+        // Restart from a releasing note.
+        instrument.note_off(440.0);
+        vels.extend(render_vel(&mut instrument, 100));
+        instrument.note_on(330.0, 1.0);
+        vels.extend(render_vel(&mut instrument, 1000));
+
+        let max_step = vels.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+        assert!(max_step < 0.01, "{}", max_step);
+        assert_eq!(voice_hz(&instrument), vec![Some(330.0)]);
+    }
+
+    #[test]
+    fn unison_spread() {
+        let unison = Unison::new(20.0, 0.5);
+        assert_eq!(unison.spread(0, 1), (0.0, 0.0));
+        assert_eq!(unison.spread(0, 3), (-10.0, -0.5));
+        assert_eq!(unison.spread(1, 3), (0.0, 0.0));
+        assert_eq!(unison.spread(2, 3), (10.0, 0.5));
+
+        let mut instrument = Instrument::new(Mono::legato().unison(unison), ()).num_voices(3);
+        instrument.note_on(440.0, 1.0);
+        let mut frames = vec![];
+        let mut frame_per_voice = instrument.frame_per_voice(44_100.0);
+        while let Some(frame) = frame_per_voice.next_voice_frame() {
+            frames.extend(frame.map(|frame| (frame.hz, frame.pan)));
+        }
+        let expected = [(-10.0f32, -0.5), (0.0, 0.0), (10.0, 0.5)];
+        assert_eq!(frames.len(), expected.len());
+        for (&(hz, pan), &(cents, expected_pan)) in frames.iter().zip(&expected) {
+            assert!((hz - 440.0 * (cents / 1200.0).exp2()).abs() < 1e-2);
+            assert_eq!(pan, expected_pan);
+        }
+    }
+
+    #[test]
+    fn note_match_cents() {
+        let note_match = NoteMatch::Cents(10.0);
+        let cents = |cents: f32| 440.0 * (cents / 1200.0).exp2();
+        assert!(note_match.does_hz_match(440.0, 440.0));
+        assert!(note_match.does_hz_match(cents(9.9), 440.0));
+        assert!(note_match.does_hz_match(cents(-9.9), 440.0));
+        assert!(!note_match.does_hz_match(cents(10.1), 440.0));
+        assert!(!note_match.does_hz_match(cents(-10.1), 440.0));
+        // The tolerance is relative, so it is equally strict in every register.
+        assert!(note_match.does_hz_match(cents(9.9) * 8.0, 3520.0));
+        assert!(!note_match.does_hz_match(cents(10.1) / 8.0, 55.0));
+
+        let mut instrument = Instrument::new(Poly::default(), ()).num_voices(1)
+            .note_off_tolerance(10.0);
+        instrument.note_on(440.0, 1.0);
+        instrument.note_off(cents(10.1));
+        assert!(instrument.voices[0].note.as_ref().unwrap().state.is_playing());
+        instrument.note_off(cents(9.9));
+        assert!(!instrument.voices[0].note.as_ref().unwrap().state.is_playing());
+    }
+}
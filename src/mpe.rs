@@ -0,0 +1,121 @@
+//! MIDI Polyphonic Expression (MPE), in which each note is played on its own MIDI channel so that
+//! its pitch bend, pressure and timbre may be controlled independently of all other notes.
+
+use midi::{Channel, Value};
+
+pub use midi::{channel, key, note_id};
+
+/// The controller number of the timbre dimension, sent on a note's member channel.
+pub const TIMBRE: Value = 74;
+
+/// The default range of the per-note pitch bend in semitones.
+pub const DEFAULT_BEND_RANGE: f32 = 48.0;
+
+/// The end of the sixteen MIDI channels occupied by a `Zone`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Side {
+    /// The master channel is the first channel and the member channels follow it.
+    Lower,
+    /// The master channel is the last channel and the member channels precede it.
+    Upper,
+}
+
+/// An MPE zone, consisting of a master channel that carries messages affecting every note in the
+/// zone and a number of member channels that each carry the notes and expression of one finger.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Zone {
+    /// The end of the channels occupied by the zone.
+    pub side: Side,
+    /// The number of member channels next to the master channel, up to `15`.
+    pub member_channels: u8,
+    /// The pitch bend in semitones of a note whose member channel is fully bent up (or down).
+    pub bend_range: f32,
+}
+
+/// The expression most recently received on a member channel, applied to the notes played on it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Expression {
+    /// The pitch bend in semitones.
+    pub bend: f32,
+    /// The pressure, from `0.0` to `1.0`.
+    pub pressure: f32,
+    /// The timbre, from `0.0` to `1.0`.
+    pub timbre: f32,
+}
+
+
+impl Zone {
+    /// A zone whose master channel is the first channel, followed by the given number of member
+    /// channels.
+    pub fn lower(member_channels: u8) -> Self {
+        Zone {
+            side: Side::Lower,
+            member_channels,
+            bend_range: DEFAULT_BEND_RANGE,
+        }
+    }
+
+    /// A zone whose master channel is the last channel, preceded by the given number of member
+    /// channels.
+    pub fn upper(member_channels: u8) -> Self {
+        Zone {
+            side: Side::Upper,
+            member_channels,
+            bend_range: DEFAULT_BEND_RANGE,
+        }
+    }
+
+    /// Set the range of the per-note pitch bend in semitones.
+    pub fn bend_range(mut self, semitones: f32) -> Self {
+        self.bend_range = semitones;
+        self
+    }
+
+    /// The channel carrying messages that affect every note in the zone.
+    pub fn master_channel(&self) -> Channel {
+        match self.side {
+            Side::Lower => 0,
+            Side::Upper => 15,
+        }
+    }
+
+    /// Whether or not the given channel is one of the zone's member channels.
+    pub fn is_member(&self, channel: Channel) -> bool {
+        let num = self.member_channels.min(15);
+        match self.side {
+            Side::Lower => channel >= 1 && channel <= num,
+            Side::Upper => channel < 15 && channel >= 15 - num,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Zone;
+
+    #[test]
+    fn lower_zone_channels() {
+        let zone = Zone::lower(3);
+        assert_eq!(zone.master_channel(), 0);
+        let members: Vec<_> = (0..16).filter(|&channel| zone.is_member(channel)).collect();
+        assert_eq!(members, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn upper_zone_channels() {
+        let zone = Zone::upper(3);
+        assert_eq!(zone.master_channel(), 15);
+        let members: Vec<_> = (0..16).filter(|&channel| zone.is_member(channel)).collect();
+        assert_eq!(members, vec![12, 13, 14]);
+    }
+
+    #[test]
+    fn member_channels_exclude_master() {
+        for zone in &[Zone::lower(20), Zone::upper(20)] {
+            assert!(!zone.is_member(zone.master_channel()));
+            assert_eq!((0..16).filter(|&channel| zone.is_member(channel)).count(), 15);
+        }
+        assert_eq!((0..16).filter(|&channel| Zone::lower(0).is_member(channel)).count(), 0);
+    }
+}
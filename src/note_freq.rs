@@ -1,9 +1,10 @@
+use envelope::Curve;
 use pitch::{self, Hz};
 use rand;
 use std;
 use time;
 use unit::NoteHz;
-use voice::{NoteState, Voice};
+use voice::Voice;
 
 /// Types designed to modulate the state of a Node.
 pub trait NoteFreqGenerator {
@@ -12,10 +13,25 @@ pub trait NoteFreqGenerator {
 
     /// Construct a new note_freq from the note_hz given by a note_event and the last voice that
     /// handled a note.
-    fn generate(&self,
-                note_hz: NoteHz,
-                detune: f32,
-                voice: Option<&Voice<Self::NoteFreq>>) -> Self::NoteFreq;
+    ///
+    /// Any randomness used to `detune` the note should be drawn from the given `rng`.
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   voice: Option<&Voice<Self::NoteFreq>>) -> Self::NoteFreq
+        where R: rand::Rng;
+
+    /// Wrap `self` with the given `Modifier`, whose offset is applied on top of the hz of each
+    /// generated note.
+    ///
+    /// Modifiers may be chained, in which case each is applied in the order it was added.
+    fn modify<M>(self, modifier: M) -> Modified<Self, M>
+        where Self: Sized,
+              M: Modifier,
+    {
+        Modified { base: self, modifier }
+    }
 }
 
 
@@ -28,9 +44,46 @@ pub trait NoteFreq: Clone + std::fmt::Debug {
 }
 
 
-/// A PortamentoNote generator that applies a glissando for the given number of samples.
+/// Types that modulate the pitch of notes produced by some other `NoteFreqGenerator`.
+///
+/// See `NoteFreqGenerator::modify`.
+pub trait Modifier {
+    /// The per-note state of the modulation.
+    type Offset: Offset;
+
+    /// Begin modulating a new note.
+    ///
+    /// Any randomness used by the modulation should be drawn from the given `rng`.
+    fn start<R>(&self, rng: &mut R) -> Self::Offset
+        where R: rand::Rng;
+}
+
+
+/// Types to be produced by `Modifier` types.
+pub trait Offset: Clone + std::fmt::Debug {
+    /// The current offset of the note's pitch in cents.
+    fn cents(&self) -> f32;
+    /// Step forward `Self` by one frame.
+    fn step(&mut self);
+}
+
+
+/// A PortamentoNote generator that applies a glissando for the given number of samples, gliding
+/// from the previous note as permitted by the `Glide`.
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Portamento(pub time::calc::Samples);
+pub struct Portamento(pub time::calc::Samples, pub Glide);
+
+/// The previous notes from which a `Portamento` glides.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Glide {
+    /// Glide from the previous note whenever it is still sounding, even if its key has been
+    /// released.
+    #[default]
+    Always,
+    /// Only glide when the new note is played while the key of the previous note is still held,
+    /// also known as "fingered" portamento.
+    Legato,
+}
 
 /// A note that interpolates between to given frequencies over the given duration.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -42,11 +95,129 @@ pub struct PortamentoFreq {
 }
 
 
+/// A generator of notes whose pitch oscillates around the note's hz, gliding to the hz from the
+/// previous note first in the same way as `Portamento`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vibrato {
+    /// The glide from the previous note's hz. A duration of `0` does not glide.
+    pub portamento: Portamento,
+    /// The oscillation of the pitch around the glide.
+    pub lfo: Lfo,
+}
+
+/// A note that glides like a `PortamentoFreq` while its pitch oscillates around the glide.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VibratoFreq {
+    pub glide: PortamentoFreq,
+    pub lfo: LfoOffset,
+}
+
+
+/// A `NoteFreqGenerator` that applies a `Modifier` on top of the notes of a `base` generator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Modified<NFG, M> {
+    pub base: NFG,
+    pub modifier: M,
+}
+
+/// A note whose pitch is offset from the hz of a `base` note.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ModifiedFreq<NF, O> {
+    pub base: NF,
+    pub offset: O,
+}
+
+/// A `Modifier` that oscillates the pitch of a note, as used by `Vibrato`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lfo {
+    /// The duration of a single cycle of the oscillation, i.e. the inverse of the vibrato rate.
+    pub period: time::calc::Samples,
+    /// The largest offset of the pitch from the note's hz in cents, reached at the peaks of the
+    /// oscillation.
+    pub depth_cents: f32,
+    /// The duration from the beginning of the note until the oscillation begins.
+    pub delay: time::calc::Samples,
+    /// The duration over which the depth of the oscillation rises from zero to `depth_cents`
+    /// once the `delay` has passed.
+    pub fade_in: time::calc::Samples,
+}
+
+/// The state of an `Lfo` for a single note.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LfoOffset {
+    pub lfo: Lfo,
+    pub current_sample: time::calc::Samples,
+}
+
+/// A `Modifier` that offsets the pitch of a note by a constant number of cents.
+///
+/// Unlike the `bend::PitchBend` of an `Instrument`, which is given in semitones and applies to
+/// every voice, the offset is fixed for the lifetime of the generator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cents(pub f32);
+
+/// A `Modifier` that slowly wanders the pitch of a note at random, in the manner of an analogue
+/// oscillator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Drift {
+    /// The largest offset of the pitch in cents.
+    pub cents: f32,
+    /// The duration over which the pitch moves between successive random offsets.
+    pub period: time::calc::Samples,
+}
+
+/// The state of a `Drift` for a single note.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DriftOffset {
+    pub drift: Drift,
+    /// Produces the sequence of random offsets visited by the note.
+    pub rng: DetuneRng,
+    /// The offset in cents at the beginning of the current period.
+    pub from_cents: f32,
+    /// The offset in cents at the end of the current period.
+    pub to_cents: f32,
+    pub current_sample: time::calc::Samples,
+}
+
+
+/// A generator of notes whose pitch begins at an offset from the note's hz and sweeps to the
+/// note's hz, as used by drums and plucks.
+///
+/// `PitchEnvelope` may also be applied on top of another generator as a `Modifier`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PitchEnvelope {
+    /// The offset of the pitch in semitones at the beginning of the note.
+    pub semitones: f32,
+    /// The duration of the sweep from the offset to the note's hz.
+    pub duration: time::calc::Samples,
+    /// The shape of the sweep.
+    pub curve: Curve,
+}
+
+/// The state of a `PitchEnvelope` for a single note.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PitchEnvelopeOffset {
+    pub envelope: PitchEnvelope,
+    pub current_sample: time::calc::Samples,
+}
+
+
+/// The default random number generator used to detune notes.
+///
+/// This is a small xorshift generator. Two renders of the same performance using the same seed
+/// produce identical detuning.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DetuneRng {
+    /// The xorshift128 state. Must never be all zero.
+    pub state: [u32; 4],
+}
+
 /// A wrapper for switching between NoteFreqGenerators at runtime.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum DynamicGenerator {
     Portamento(Portamento),
     Constant,
+    Vibrato(Vibrato),
 }
 
 /// A warpper for switching between different NoteFreqs at runtime.
@@ -54,22 +225,210 @@ pub enum DynamicGenerator {
 pub enum Dynamic {
     Portamento(PortamentoFreq),
     Constant(pitch::calc::Hz),
+    Vibrato(VibratoFreq),
 }
 
 
 impl DynamicGenerator {
     /// Construct a default portamento.
     pub fn portamento(samples: time::calc::Samples) -> DynamicGenerator {
-        DynamicGenerator::Portamento(Portamento(samples))
+        DynamicGenerator::Portamento(Portamento::always(samples))
+    }
+
+    /// Construct a portamento that only glides between overlapping notes.
+    pub fn legato_portamento(samples: time::calc::Samples) -> DynamicGenerator {
+        DynamicGenerator::Portamento(Portamento::legato(samples))
+    }
+
+    /// Construct a vibrato with the given period and depth in cents.
+    pub fn vibrato(period: time::calc::Samples, depth_cents: f32) -> DynamicGenerator {
+        DynamicGenerator::Vibrato(Vibrato::new(period, depth_cents))
+    }
+}
+
+
+impl Portamento {
+    /// Construct a `Portamento` that always glides from the previous note.
+    pub fn always(samples: time::calc::Samples) -> Self {
+        Portamento(samples, Glide::Always)
+    }
+
+    /// Construct a `Portamento` that only glides from the previous note while its key is held.
+    pub fn legato(samples: time::calc::Samples) -> Self {
+        Portamento(samples, Glide::Legato)
+    }
+}
+
+
+impl Glide {
+    /// The hz of the note on the given voice from which a new note should glide, if any.
+    fn start_hz<NF>(&self, maybe_voice: Option<&Voice<NF>>) -> Option<pitch::calc::Hz>
+        where NF: NoteFreq,
+    {
+        let glide = *self;
+        maybe_voice
+            .and_then(|voice| voice.note.as_ref())
+            .filter(|note| match glide {
+                Glide::Always => true,
+                Glide::Legato => note.state.is_playing() && !note.sustained,
+            })
+            .map(|note| note.freq.hz())
+    }
+}
+
+
+impl Vibrato {
+    /// Construct a new `Vibrato` with the given period and depth in cents that begins
+    /// immediately at full depth and does not glide.
+    pub fn new(period: time::calc::Samples, depth_cents: f32) -> Self {
+        Vibrato {
+            portamento: Portamento::always(0),
+            lfo: Lfo::new(period, depth_cents),
+        }
+    }
+
+    /// Glide to each note from the previous note's hz over the given duration.
+    pub fn portamento(mut self, samples: time::calc::Samples) -> Self {
+        self.portamento = Portamento::always(samples);
+        self
+    }
+
+    /// Glide to each note from the previous note's hz over the given duration, but only while the
+    /// key of the previous note is held.
+    pub fn legato_portamento(mut self, samples: time::calc::Samples) -> Self {
+        self.portamento = Portamento::legato(samples);
+        self
+    }
+
+    /// Begin the oscillation once the given duration has passed since the beginning of the note.
+    pub fn delay(mut self, samples: time::calc::Samples) -> Self {
+        self.lfo = self.lfo.delay(samples);
+        self
+    }
+
+    /// Raise the depth of the oscillation from zero over the given duration once it begins.
+    pub fn fade_in(mut self, samples: time::calc::Samples) -> Self {
+        self.lfo = self.lfo.fade_in(samples);
+        self
+    }
+}
+
+
+impl Lfo {
+    /// Construct a new `Lfo` with the given period and depth in cents that begins immediately at
+    /// full depth.
+    pub fn new(period: time::calc::Samples, depth_cents: f32) -> Self {
+        Lfo {
+            period,
+            depth_cents,
+            delay: 0,
+            fade_in: 0,
+        }
+    }
+
+    /// Begin the oscillation once the given duration has passed since the beginning of the note.
+    pub fn delay(mut self, samples: time::calc::Samples) -> Self {
+        self.delay = samples;
+        self
+    }
+
+    /// Raise the depth of the oscillation from zero over the given duration once it begins.
+    pub fn fade_in(mut self, samples: time::calc::Samples) -> Self {
+        self.fade_in = samples;
+        self
+    }
+}
+
+
+impl Drift {
+    /// Construct a new `Drift` of up to the given number of cents, moving to a new random offset
+    /// every `period` samples.
+    pub fn new(cents: f32, period: time::calc::Samples) -> Self {
+        Drift { cents, period }
+    }
+
+    /// A random offset in cents within the range of the drift.
+    fn random_cents<R>(&self, rng: &mut R) -> f32
+        where R: rand::Rng,
+    {
+        (rng.gen::<f32>() * 2.0 - 1.0) * self.cents
+    }
+}
+
+
+impl PitchEnvelope {
+    /// Construct a new `PitchEnvelope` that sweeps linearly from the given offset in semitones
+    /// over the given duration.
+    pub fn new(semitones: f32, duration: time::calc::Samples) -> Self {
+        PitchEnvelope {
+            semitones,
+            duration,
+            curve: Curve::Linear,
+        }
+    }
+
+    /// Sweep with the given `Curve`.
+    pub fn curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+}
+
+
+impl DetuneRng {
+    /// The seed used by `DetuneRng::default`.
+    pub const DEFAULT_SEED: u64 = 0;
+
+    /// Construct a new `DetuneRng` from the given seed.
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed across the whole state using splitmix64, which never produces an
+        // all-zero state for the xorshift generator.
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let (a, b) = (next(), next());
+        DetuneRng { state: [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32] }
+    }
+}
+
+impl Default for DetuneRng {
+    fn default() -> Self {
+        DetuneRng::new(DetuneRng::DEFAULT_SEED)
+    }
+}
+
+impl rand::Rng for DetuneRng {
+    fn next_u32(&mut self) -> u32 {
+        let [x, y, z, w] = self.state;
+        let t = x ^ (x << 11);
+        let next = w ^ (w >> 19) ^ t ^ (t >> 8);
+        self.state = [y, z, w, next];
+        next
+    }
+}
+
+impl rand::SeedableRng<u64> for DetuneRng {
+    fn reseed(&mut self, seed: u64) {
+        *self = DetuneRng::new(seed);
+    }
+    fn from_seed(seed: u64) -> Self {
+        DetuneRng::new(seed)
     }
 }
 
 
 /// Generate a constant `Hz` frequency.
-fn generate_constant_freq(note_hz: NoteHz, detune: f32) -> pitch::calc::Hz {
+fn generate_constant_freq<R>(note_hz: NoteHz, detune: f32, rng: &mut R) -> pitch::calc::Hz
+    where R: rand::Rng,
+{
     // If some detune was given, slightly detune the note_hz.
     if detune > 0.0 {
-        let step_offset = rand::random::<f32>() * 2.0 * detune - detune;
+        let step_offset = rng.gen::<f32>() * 2.0 * detune - detune;
         pitch::Step(Hz(note_hz).step() + step_offset).hz()
     // Otherwise, our target_hz is the given note_hz.
     } else {
@@ -79,13 +438,15 @@ fn generate_constant_freq(note_hz: NoteHz, detune: f32) -> pitch::calc::Hz {
 
 
 /// Generate a portamento frequency.
-fn generate_portamento_freq(portamento_samples: time::calc::Samples,
-                            note_hz: NoteHz,
-                            detune: f32,
-                            maybe_last_hz: Option<pitch::calc::Hz>) -> PortamentoFreq {
-
+fn generate_portamento_freq<R>(portamento_samples: time::calc::Samples,
+                               note_hz: NoteHz,
+                               detune: f32,
+                               rng: &mut R,
+                               maybe_last_hz: Option<pitch::calc::Hz>) -> PortamentoFreq
+    where R: rand::Rng,
+{
     // If some detune was given, slightly detune the note_hz.
-    let target_hz = generate_constant_freq(note_hz, detune);
+    let target_hz = generate_constant_freq(note_hz, detune, rng);
 
     PortamentoFreq {
         current_sample: 0,
@@ -96,13 +457,32 @@ fn generate_portamento_freq(portamento_samples: time::calc::Samples,
 }
 
 
+/// Generate a vibrato frequency.
+fn generate_vibrato_freq<R>(vibrato: Vibrato,
+                            note_hz: NoteHz,
+                            detune: f32,
+                            rng: &mut R,
+                            maybe_last_hz: Option<pitch::calc::Hz>) -> VibratoFreq
+    where R: rand::Rng,
+{
+    let Vibrato { portamento: Portamento(portamento, _), lfo } = vibrato;
+    VibratoFreq {
+        glide: generate_portamento_freq(portamento, note_hz, detune, rng, maybe_last_hz),
+        lfo: lfo.start(rng),
+    }
+}
+
+
 impl NoteFreqGenerator for () {
     type NoteFreq = pitch::calc::Hz;
-    fn generate(&self,
-                note_hz: NoteHz,
-                detune: f32,
-                _voice: Option<&Voice<pitch::calc::Hz>>) -> pitch::calc::Hz {
-        generate_constant_freq(note_hz, detune)
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   _voice: Option<&Voice<pitch::calc::Hz>>) -> pitch::calc::Hz
+        where R: rand::Rng,
+    {
+        generate_constant_freq(note_hz, detune, rng)
     }
 }
 
@@ -114,23 +494,20 @@ impl NoteFreq for pitch::calc::Hz {
 
 impl NoteFreqGenerator for Portamento {
     type NoteFreq = PortamentoFreq;
-    fn generate(&self,
-                note_hz: NoteHz,
-                detune: f32,
-                maybe_voice: Option<&Voice<PortamentoFreq>>) -> PortamentoFreq {
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   maybe_voice: Option<&Voice<PortamentoFreq>>) -> PortamentoFreq
+        where R: rand::Rng,
+    {
 
-        let Portamento(duration_samples) = *self;
+        let Portamento(duration_samples, glide) = *self;
 
         // If some note is already playing, take it to use for portamento.
-        let maybe_last_hz = match maybe_voice {
-            Some(voice) => match voice.note.as_ref() {
-                Some(note) if note.state == NoteState::Playing => Some(note.freq.hz()),
-                _ => None,
-            },
-            None => None,
-        };
+        let maybe_last_hz = glide.start_hz(maybe_voice);
 
-        generate_portamento_freq(duration_samples, note_hz, detune, maybe_last_hz)
+        generate_portamento_freq(duration_samples, note_hz, detune, rng, maybe_last_hz)
     }
 }
 
@@ -156,27 +533,215 @@ impl NoteFreq for PortamentoFreq {
 }
 
 
+impl NoteFreqGenerator for Vibrato {
+    type NoteFreq = VibratoFreq;
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   maybe_voice: Option<&Voice<VibratoFreq>>) -> VibratoFreq
+        where R: rand::Rng,
+    {
+        // If some note is already playing, take it to use for portamento.
+        let Portamento(_, glide) = self.portamento;
+        let maybe_last_hz = glide.start_hz(maybe_voice);
+        generate_vibrato_freq(*self, note_hz, detune, rng, maybe_last_hz)
+    }
+}
+
+impl NoteFreq for VibratoFreq {
+    fn hz(&self) -> pitch::calc::Hz {
+        self.glide.hz() * (self.lfo.cents() / 1200.0).exp2()
+    }
+    fn next_hz(&mut self) -> pitch::calc::Hz {
+        let hz = self.glide.next_hz() * (self.lfo.cents() / 1200.0).exp2();
+        self.lfo.step();
+        hz
+    }
+}
+
+
+impl<NFG, M> NoteFreqGenerator for Modified<NFG, M>
+    where NFG: NoteFreqGenerator,
+          M: Modifier,
+{
+    type NoteFreq = ModifiedFreq<NFG::NoteFreq, M::Offset>;
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   maybe_voice: Option<&Voice<Self::NoteFreq>>) -> Self::NoteFreq
+        where R: rand::Rng,
+    {
+        // The base generator only knows about the base of the voice's current note.
+        let maybe_base_voice = maybe_voice.map(|voice| voice.map_freq(|freq| freq.base.clone()));
+        ModifiedFreq {
+            base: self.base.generate(note_hz, detune, rng, maybe_base_voice.as_ref()),
+            offset: self.modifier.start(rng),
+        }
+    }
+}
+
+impl<NF, O> NoteFreq for ModifiedFreq<NF, O>
+    where NF: NoteFreq,
+          O: Offset,
+{
+    fn hz(&self) -> pitch::calc::Hz {
+        self.base.hz() * (self.offset.cents() / 1200.0).exp2()
+    }
+    fn next_hz(&mut self) -> pitch::calc::Hz {
+        let hz = self.base.next_hz() * (self.offset.cents() / 1200.0).exp2();
+        self.offset.step();
+        hz
+    }
+}
+
+
+impl Modifier for Lfo {
+    type Offset = LfoOffset;
+    fn start<R>(&self, _rng: &mut R) -> LfoOffset
+        where R: rand::Rng,
+    {
+        LfoOffset { lfo: *self, current_sample: 0 }
+    }
+}
+
+impl Offset for LfoOffset {
+    fn cents(&self) -> f32 {
+        let Lfo { period, depth_cents, delay, fade_in } = self.lfo;
+        if period <= 0 || self.current_sample < delay {
+            return 0.0;
+        }
+        let elapsed = self.current_sample - delay;
+        let depth = if elapsed < fade_in {
+            depth_cents * (elapsed as f32 / fade_in as f32)
+        } else {
+            depth_cents
+        };
+        let phase = (elapsed % period) as f64 / period as f64;
+        depth * (phase * 2.0 * std::f64::consts::PI).sin() as f32
+    }
+    fn step(&mut self) {
+        self.current_sample += 1;
+    }
+}
+
+
+impl Modifier for Cents {
+    type Offset = Cents;
+    fn start<R>(&self, _rng: &mut R) -> Cents
+        where R: rand::Rng,
+    {
+        *self
+    }
+}
+
+impl Offset for Cents {
+    fn cents(&self) -> f32 { self.0 }
+    fn step(&mut self) {}
+}
+
+
+impl Modifier for Drift {
+    type Offset = DriftOffset;
+    fn start<R>(&self, rng: &mut R) -> DriftOffset
+        where R: rand::Rng,
+    {
+        let mut rng = DetuneRng::new(rng.gen());
+        let from_cents = self.random_cents(&mut rng);
+        let to_cents = self.random_cents(&mut rng);
+        DriftOffset { drift: *self, rng, from_cents, to_cents, current_sample: 0 }
+    }
+}
+
+impl Offset for DriftOffset {
+    fn cents(&self) -> f32 {
+        let period = self.drift.period;
+        if period <= 0 {
+            return 0.0;
+        }
+        let perc = (self.current_sample % period) as f32 / period as f32;
+        self.from_cents + (self.to_cents - self.from_cents) * perc
+    }
+    fn step(&mut self) {
+        self.current_sample += 1;
+        // Move on to the next random offset at the end of each period.
+        let period = self.drift.period;
+        if period > 0 && self.current_sample % period == 0 {
+            self.from_cents = self.to_cents;
+            self.to_cents = self.drift.random_cents(&mut self.rng);
+        }
+    }
+}
+
+
+impl NoteFreqGenerator for PitchEnvelope {
+    type NoteFreq = ModifiedFreq<pitch::calc::Hz, PitchEnvelopeOffset>;
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   _voice: Option<&Voice<Self::NoteFreq>>) -> Self::NoteFreq
+        where R: rand::Rng,
+    {
+        ModifiedFreq {
+            base: generate_constant_freq(note_hz, detune, rng),
+            offset: self.start(rng),
+        }
+    }
+}
+
+impl Modifier for PitchEnvelope {
+    type Offset = PitchEnvelopeOffset;
+    fn start<R>(&self, _rng: &mut R) -> PitchEnvelopeOffset
+        where R: rand::Rng,
+    {
+        PitchEnvelopeOffset { envelope: *self, current_sample: 0 }
+    }
+}
+
+impl Offset for PitchEnvelopeOffset {
+    fn cents(&self) -> f32 {
+        let PitchEnvelope { semitones, duration, curve } = self.envelope;
+        if self.current_sample >= duration {
+            return 0.0;
+        }
+        let perc = self.current_sample as f32 / duration as f32;
+        semitones * 100.0 * (1.0 - curve.apply(perc))
+    }
+    fn step(&mut self) {
+        if self.current_sample < self.envelope.duration {
+            self.current_sample += 1;
+        }
+    }
+}
+
+
 impl NoteFreqGenerator for DynamicGenerator {
     type NoteFreq = Dynamic;
-    fn generate(&self,
-                note_hz: NoteHz,
-                detune: f32,
-                maybe_voice: Option<&Voice<Dynamic>>) -> Dynamic {
+    fn generate<R>(&self,
+                   note_hz: NoteHz,
+                   detune: f32,
+                   rng: &mut R,
+                   maybe_voice: Option<&Voice<Dynamic>>) -> Dynamic
+        where R: rand::Rng,
+    {
         match *self {
-            DynamicGenerator::Portamento(Portamento(portamento_ms)) => {
+            DynamicGenerator::Portamento(Portamento(portamento_ms, glide)) => {
                 // If some note is already playing, take it to use for portamento.
-                let maybe_last_hz = match maybe_voice {
-                    Some(voice) => match voice.note.as_ref() {
-                        Some(note) if note.state == NoteState::Playing => Some(note.freq.hz()),
-                        _ => None,
-                    },
-                    None => None,
-                };
-                let freq = generate_portamento_freq(portamento_ms, note_hz, detune, maybe_last_hz);
+                let maybe_last_hz = glide.start_hz(maybe_voice);
+                let freq =
+                    generate_portamento_freq(portamento_ms, note_hz, detune, rng, maybe_last_hz);
                 Dynamic::Portamento(freq)
             },
             DynamicGenerator::Constant =>
-                Dynamic::Constant(generate_constant_freq(note_hz, detune)),
+                Dynamic::Constant(generate_constant_freq(note_hz, detune, rng)),
+            DynamicGenerator::Vibrato(vibrato) => {
+                let Portamento(_, glide) = vibrato.portamento;
+                let maybe_last_hz = glide.start_hz(maybe_voice);
+                let freq = generate_vibrato_freq(vibrato, note_hz, detune, rng, maybe_last_hz);
+                Dynamic::Vibrato(freq)
+            },
         }
     }
 }
@@ -186,12 +751,128 @@ impl NoteFreq for Dynamic {
         match *self {
             Dynamic::Portamento(ref porta) => porta.hz(),
             Dynamic::Constant(ref hz) => hz.hz(),
+            Dynamic::Vibrato(ref vibrato) => vibrato.hz(),
         }
     }
     fn next_hz(&mut self) -> pitch::calc::Hz {
         match *self {
             Dynamic::Portamento(ref mut porta) => porta.next_hz(),
             Dynamic::Constant(ref mut hz)      => hz.next_hz(),
+            Dynamic::Vibrato(ref mut vibrato)  => vibrato.next_hz(),
         }
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use Instrument;
+    use envelope::Curve;
+    use mode::Poly;
+    use super::{Cents, DetuneRng, Drift, DynamicGenerator, Lfo, Modifier, NoteFreq,
+                NoteFreqGenerator, Offset, PitchEnvelope, Portamento, Vibrato};
+
+    /// The cents of the given offset over the given number of frames.
+    fn offset_cents<O>(mut offset: O, frames: usize) -> Vec<f32>
+        where O: Offset,
+    {
+        (0..frames).map(|_| {
+            let cents = offset.cents();
+            offset.step();
+            cents
+        }).collect()
+    }
+
+    /// The hz of the given note over the given number of frames.
+    fn note_hz<NF>(mut freq: NF, frames: usize) -> Vec<f32>
+        where NF: NoteFreq,
+    {
+        (0..frames).map(|_| freq.next_hz()).collect()
+    }
+
+    #[test]
+    fn lfo_delay_and_fade_in() {
+        let lfo = Lfo::new(100, 10.0).delay(50).fade_in(200);
+        let cents = offset_cents(lfo.start(&mut DetuneRng::default()), 500);
+        assert!(cents[..51].iter().all(|&cents| cents == 0.0));
+        // A quarter of the way into the first cycle, the depth has faded in by an eighth.
+        assert!((cents[75] - 1.25).abs() < 1e-3);
+        assert!(cents[50..250].iter().all(|&cents| cents.abs() < 10.0));
+        // Once faded in, the oscillation peaks at the full depth.
+        assert!((cents[275] - 10.0).abs() < 1e-3);
+        assert!((cents[325] + 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cents_modifiers_chain() {
+        let mut rng = DetuneRng::default();
+        let octave = ().modify(Cents(1200.0));
+        assert!((octave.generate(440.0, 0.0, &mut rng, None).hz() - 880.0).abs() < 1e-3);
+        let fourth = ().modify(Cents(1200.0)).modify(Cents(-700.0));
+        let expected = 440.0 * (500.0f32 / 1200.0).exp2();
+        assert!((fourth.generate(440.0, 0.0, &mut rng, None).hz() - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn drift_is_bounded_and_seeded() {
+        let drift = Drift::new(20.0, 100);
+        let cents = offset_cents(drift.start(&mut DetuneRng::new(3)), 1000);
+        assert!(cents.iter().all(|&cents| cents.abs() <= 20.0));
+        assert!(cents.iter().any(|&other| other != cents[0]));
+        assert_eq!(cents, offset_cents(drift.start(&mut DetuneRng::new(3)), 1000));
+        assert!(cents != offset_cents(drift.start(&mut DetuneRng::new(4)), 1000));
+    }
+
+    #[test]
+    fn vibrato_is_a_modified_portamento() {
+        let mut rng = DetuneRng::default();
+        let vibrato = Vibrato::new(100, 30.0).delay(10).fade_in(20);
+        let modified = Portamento::always(0).modify(Lfo::new(100, 30.0).delay(10).fade_in(20));
+        let vibrato_hz = note_hz(vibrato.generate(440.0, 0.0, &mut rng, None), 300);
+        let modified_hz = note_hz(modified.generate(440.0, 0.0, &mut rng, None), 300);
+        assert_eq!(vibrato_hz, modified_hz);
+    }
+
+    #[test]
+    fn pitch_envelope_sweep() {
+        let envelope = PitchEnvelope::new(12.0, 100);
+        let hz = note_hz(envelope.generate(440.0, 0.0, &mut DetuneRng::default(), None), 200);
+        assert!((hz[0] - 880.0).abs() < 1e-2);
+        // Half way through a linear sweep the note is offset by half of the semitones.
+        assert!((hz[50] - 440.0 * 0.5f32.exp2()).abs() < 1e-2);
+        assert!(hz[..100].windows(2).all(|w| w[1] < w[0]));
+        assert!(hz[100..].iter().all(|&hz| (hz - 440.0).abs() < 1e-3));
+
+        // The curve shapes the sweep but not its start or its end.
+        let curved = envelope.curve(Curve::Exponential);
+        let cents = offset_cents(curved.start(&mut DetuneRng::default()), 101);
+        assert_eq!(cents[0], 1200.0);
+        assert!(cents[50] < 600.0);
+        assert_eq!(cents[100], 0.0);
+    }
+
+    /// The hz of a note played while the previous note is releasing, in its first frame.
+    fn hz_after_release<NFG>(note_freq_gen: NFG) -> f32
+        where NFG: NoteFreqGenerator,
+    {
+        let mut instrument = Instrument::new(Poly::default(), note_freq_gen).release(100.0);
+        instrument.note_on(100.0, 1.0);
+        instrument.note_off(100.0);
+        instrument.note_on(200.0, 1.0);
+        match instrument.frame_per_voice(44_100.0).next() {
+            Some(Some((_, hz))) => hz,
+            _ => panic!("no note is sounding"),
+        }
+    }
+
+    #[test]
+    fn vibrato_glide() {
+        let vibrato = Vibrato::new(100, 0.0);
+        assert!((hz_after_release(vibrato.portamento(100)) - 100.0).abs() < 1e-2);
+        assert!((hz_after_release(vibrato.legato_portamento(100)) - 200.0).abs() < 1e-2);
+        let dynamic = DynamicGenerator::Vibrato(vibrato.portamento(100));
+        assert!((hz_after_release(dynamic) - 100.0).abs() < 1e-2);
+        let dynamic = DynamicGenerator::Vibrato(vibrato.legato_portamento(100));
+        assert!((hz_after_release(dynamic) - 200.0).abs() < 1e-2);
+    }
+}
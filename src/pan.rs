@@ -0,0 +1,125 @@
+//! Positioning of voices across the channels of a multi-channel output.
+
+use pitch::{self, Hz};
+use std;
+use unit::{NoteHz, Pan};
+
+/// Describes how a `Pan` position is converted into the gain of each output channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Law {
+    /// The gain of each channel changes linearly with the pan, so that a centered voice is
+    /// attenuated by 6dB in each of the two nearest channels.
+    Linear,
+    /// The gain of each channel follows a quarter sine, so that the perceived loudness of a voice
+    /// remains constant as it moves between channels.
+    #[default]
+    ConstantPower,
+}
+
+/// Pans each note according to its pitch, in the same way that low notes sound to the left and
+/// high notes sound to the right of a pianist.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyTrack {
+    /// The pitch (as a MIDI step where `69.0` is A4) of notes that are centered.
+    pub center: pitch::calc::Step,
+    /// The distance from the `center` in semitones at which notes are panned fully to one side.
+    pub semitones: f32,
+}
+
+
+impl KeyTrack {
+    /// Construct a new `KeyTrack` around the given `center` step.
+    pub fn new(center: pitch::calc::Step, semitones: f32) -> Self {
+        KeyTrack { center, semitones }
+    }
+
+    /// The pan of a note with the given hz.
+    pub fn pan(&self, hz: NoteHz) -> Pan {
+        if self.semitones <= 0.0 {
+            return 0.0;
+        }
+        clamp((Hz(hz).step() - self.center) / self.semitones)
+    }
+}
+
+
+/// Clamp the given pan to the range `-1.0...1.0`.
+pub fn clamp(pan: Pan) -> Pan {
+    pan.clamp(-1.0, 1.0)
+}
+
+/// The gain of the given `channel` for a voice at the given `pan`.
+///
+/// The `num_channels` channels are assumed to be spread evenly from left (the first channel) to
+/// right (the last channel), so that a voice is shared between the two channels nearest to it.
+pub fn channel_gain(pan: Pan, law: Law, channel: usize, num_channels: usize) -> f32 {
+    if num_channels < 2 {
+        return 1.0;
+    }
+    let position = (clamp(pan) + 1.0) / 2.0 * (num_channels - 1) as f32;
+    let distance = (position - channel as f32).abs();
+    if distance >= 1.0 {
+        return 0.0;
+    }
+    match law {
+        Law::Linear => 1.0 - distance,
+        Law::ConstantPower => (distance * std::f32::consts::FRAC_PI_2).cos(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use pitch;
+    use std;
+    use super::{channel_gain, KeyTrack, Law};
+
+    /// Assert that a voice at the given pan has the given gain in each channel.
+    fn assert_gains(pan: f32, law: Law, gains: &[f32]) {
+        for (channel, &expected) in gains.iter().enumerate() {
+            let gain = channel_gain(pan, law, channel, gains.len());
+            assert!((gain - expected).abs() < 1e-6, "{}: {} != {}", channel, gain, expected);
+        }
+    }
+
+    #[test]
+    fn stereo_gain() {
+        assert_gains(-1.0, Law::Linear, &[1.0, 0.0]);
+        assert_gains(0.0, Law::Linear, &[0.5, 0.5]);
+        assert_gains(1.0, Law::Linear, &[0.0, 1.0]);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_gains(0.0, Law::ConstantPower, &[half, half]);
+        assert_gains(2.0, Law::ConstantPower, &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn constant_power() {
+        for step in 0..21 {
+            let pan = step as f32 / 10.0 - 1.0;
+            let left = channel_gain(pan, Law::ConstantPower, 0, 2);
+            let right = channel_gain(pan, Law::ConstantPower, 1, 2);
+            assert!((left * left + right * right - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn multi_channel_gain() {
+        // A voice is shared between the two channels nearest to it.
+        assert_gains(0.0, Law::Linear, &[0.0, 0.5, 0.5, 0.0]);
+        assert_gains(-1.0 / 3.0, Law::Linear, &[0.0, 1.0, 0.0, 0.0]);
+        assert_gains(0.0, Law::Linear, &[0.0, 1.0, 0.0]);
+        assert_gains(0.5, Law::Linear, &[1.0]);
+    }
+
+    #[test]
+    fn key_track() {
+        let key_track = KeyTrack::new(60.0, 12.0);
+        let hz = |step: f32| pitch::Step(step).hz();
+        assert!(key_track.pan(hz(60.0)).abs() < 1e-3);
+        assert!((key_track.pan(hz(66.0)) - 0.5).abs() < 1e-3);
+        assert!((key_track.pan(hz(54.0)) + 0.5).abs() < 1e-3);
+        assert_eq!(key_track.pan(hz(96.0)), 1.0);
+        assert_eq!(key_track.pan(hz(24.0)), -1.0);
+        assert_eq!(KeyTrack::new(60.0, 0.0).pan(hz(72.0)), 0.0);
+    }
+}
@@ -3,14 +3,42 @@ extern crate serde;
 mod note_freq {
 
     mod portamento {
-        use note_freq::Portamento;
+        use note_freq::{Glide, Portamento};
         use super::super::serde;
+        use time::calc::Samples;
 
         impl serde::Serialize for Portamento {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
-                serializer.serialize_newtype_struct("Portamento", self.0)
+                struct Visitor<'a> {
+                    t: &'a Portamento,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::SeqVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.0))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.1))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_tuple_struct("Portamento", Visitor { t: self, field_idx: 0 })
             }
         }
 
@@ -23,20 +51,43 @@ mod note_freq {
                 impl serde::de::Visitor for Visitor {
                     type Value = Portamento;
 
+                    // `Portamento` was once a newtype of its duration, in which case it always
+                    // glides.
                     fn visit_i64<E>(&mut self, v: i64) -> Result<Self::Value, E>
                         where E: serde::de::Error,
                     {
-                        Ok(Portamento(v))
+                        Ok(Portamento(v, Glide::Always))
                     }
 
-                    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
-                        where D: serde::Deserializer,
+                    fn visit_u64<E>(&mut self, v: u64) -> Result<Self::Value, E>
+                        where E: serde::de::Error,
+                    {
+                        Ok(Portamento(v as Samples, Glide::Always))
+                    }
+
+                    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Portamento, V::Error>
+                        where V: serde::de::SeqVisitor,
                     {
-                        Ok(Portamento(try!(serde::de::Deserialize::deserialize(deserializer))))
+                        let samples = try!(visitor.visit());
+                        let glide = try!(visitor.visit());
+
+                        let samples = match samples {
+                            Some(samples) => samples,
+                            None => return Err(serde::de::Error::missing_field("samples")),
+                        };
+
+                        let glide = match glide {
+                            Some(glide) => glide,
+                            None => return Err(serde::de::Error::missing_field("glide")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Portamento(samples, glide))
                     }
                 }
 
-                deserializer.deserialize_newtype_struct("Portamento", Visitor)
+                deserializer.deserialize_tuple_struct("Portamento", 2, Visitor)
             }
         }
 
@@ -44,17 +95,115 @@ mod note_freq {
         fn test() {
             extern crate serde_json;
 
-            let portamento = Portamento(1000);
+            let portamento = Portamento(1000, Glide::Legato);
             let serialized = serde_json::to_string(&portamento).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("1000", &serialized);
+            assert_eq!("[1000,{\"Legato\":[]}]", &serialized);
             
             let deserialized: Portamento = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
             assert_eq!(portamento, deserialized);
+
+            let samples: Portamento = serde_json::from_str("1000").unwrap();
+            assert_eq!(Portamento(1000, Glide::Always), samples);
+        }
+    }
+
+    mod glide {
+        use note_freq::Glide;
+        use super::super::serde;
+
+        impl serde::Serialize for Glide {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Glide::Always =>
+                        serializer.serialize_unit_variant("Glide", 0, "Always"),
+                    Glide::Legato =>
+                        serializer.serialize_unit_variant("Glide", 1, "Legato"),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Glide {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Always,
+                    Legato,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Always" => Ok(Variant::Always),
+                                    "Legato" => Ok(Variant::Legato),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Glide;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Always => {
+                                try!(visitor.visit_unit());
+                                Ok(Glide::Always)
+                            },
+                            Variant::Legato => {
+                                try!(visitor.visit_unit());
+                                Ok(Glide::Legato)
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Always", "Legato"];
+
+                deserializer.deserialize_enum("Glide", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let glide = Glide::Legato;
+            let serialized = serde_json::to_string(&glide).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Legato\":[]}", serialized);
+
+            let deserialized: Glide = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(glide, deserialized);
         }
+
     }
 
     mod portamento_freq {
@@ -226,7 +375,7 @@ mod note_freq {
             let serialized = serde_json::to_string(&porta_freq).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("{\"current_sample\":0,\"target_samples\":10000,\"start_mel\":10,\"target_mel\":20}", serialized);
+            assert_eq!("{\"current_sample\":0,\"target_samples\":10000,\"start_mel\":10.0,\"target_mel\":20.0}", serialized);
             
             let deserialized: PortamentoFreq = serde_json::from_str(&serialized).unwrap();
 
@@ -236,142 +385,3184 @@ mod note_freq {
 
     }
 
-    mod dynamic_generator {
-        use note_freq::DynamicGenerator;
+    mod pitch_envelope {
+        use note_freq::PitchEnvelope;
         use super::super::serde;
 
-        impl serde::Serialize for DynamicGenerator {
+        impl serde::Serialize for PitchEnvelope {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
-                match *self {
-                    DynamicGenerator::Portamento(p) =>
-                        serializer.serialize_newtype_variant("DynamicGenrator", 0, "Portamento", p),
-                    DynamicGenerator::Constant =>
-                        serializer.serialize_unit_variant("DynamicGenrator", 1, "Constant"),
+                struct Visitor<'a> {
+                    t: &'a PitchEnvelope,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("semitones",
+                                                                             self.t.semitones))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("duration",
+                                                                             self.t.duration))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("curve",
+                                                                             self.t.curve))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(3)
+                    }
                 }
+
+                serializer.serialize_struct("PitchEnvelope", Visitor { t: self, field_idx: 0 })
             }
         }
 
-        impl serde::Deserialize for DynamicGenerator {
+        impl serde::Deserialize for PitchEnvelope {
             fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
                 where D: serde::Deserializer,
             {
-                enum Variant {
-                    Portamento,
-                    Constant,
-                }
+                struct Visitor;
 
-                impl serde::de::Deserialize for Variant {
-                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
-                        where D: serde::Deserializer,
+                impl serde::de::Visitor for Visitor {
+                    type Value = PitchEnvelope;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<PitchEnvelope, V::Error>
+                        where V: serde::de::MapVisitor,
                     {
-                        struct VariantVisitor;
+                        let mut semitones = None;
+                        let mut duration = None;
+                        let mut curve = None;
 
-                        impl serde::de::Visitor for VariantVisitor {
-                            type Value = Variant;
+                        enum Field {
+                            Semitones,
+                            Duration,
+                            Curve,
+                        }
 
-                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
-                                where E: serde::de::Error,
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
                             {
-                                match value {
-                                    "Portamento" => Ok(Variant::Portamento),
-                                    "Constant" => Ok(Variant::Constant),
-                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "semitones" => Ok(Field::Semitones),
+                                            "duration" => Ok(Field::Duration),
+                                            "curve" => Ok(Field::Curve),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected semitones, duration or curve"
+                                            )),
+                                        }
+                                    }
                                 }
+
+                                deserializer.deserialize(FieldVisitor)
                             }
                         }
 
-                        deserializer.deserialize(VariantVisitor)
-                    }
-                }
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Semitones) => { semitones = Some(try!(visitor.visit_value())); },
+                                Some(Field::Duration) => { duration = Some(try!(visitor.visit_value())); },
+                                Some(Field::Curve) => { curve = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
 
-                struct Visitor;
+                        let semitones = match semitones {
+                            Some(semitones) => semitones,
+                            None => return Err(serde::de::Error::missing_field("semitones")),
+                        };
 
-                impl serde::de::EnumVisitor for Visitor {
-                    type Value = DynamicGenerator;
+                        let duration = match duration {
+                            Some(duration) => duration,
+                            None => return Err(serde::de::Error::missing_field("duration")),
+                        };
 
-                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
-                        where V: serde::de::VariantVisitor,
-                    {
-                        match try!(visitor.visit_variant()) {
-                            Variant::Portamento => {
-                                let porta = try!(visitor.visit_newtype());
-                                Ok(DynamicGenerator::Portamento(porta))
-                            },
-                            Variant::Constant => {
-                                try!(visitor.visit_unit());
-                                Ok(DynamicGenerator::Constant)
-                            },
-                        }
+                        let curve = match curve {
+                            Some(curve) => curve,
+                            None => return Err(serde::de::Error::missing_field("curve")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(PitchEnvelope {
+                            semitones: semitones,
+                            duration: duration,
+                            curve: curve,
+                        })
                     }
                 }
 
-                const VARIANTS: &'static [&'static str] = &["Portamento", "Constant"];
+                static FIELDS: &'static [&'static str] = &[
+                    "semitones",
+                    "duration",
+                    "curve",
+                ];
 
-                deserializer.deserialize_enum("DynamicGenerator", VARIANTS, Visitor)
+                deserializer.deserialize_struct("PitchEnvelope", FIELDS, Visitor)
             }
         }
 
         #[test]
         fn test() {
-            use note_freq::Portamento;
+            use envelope::Curve;
             extern crate serde_json;
 
-            let porta_freq = DynamicGenerator::Portamento(Portamento(20));
-            let serialized = serde_json::to_string(&porta_freq).unwrap();
+            let pitch_envelope = PitchEnvelope::new(12.5, 4410).curve(Curve::Exponential);
+            let serialized = serde_json::to_string(&pitch_envelope).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("{\"Portamento\":20}", serialized);
-            
-            let deserialized: DynamicGenerator = serde_json::from_str(&serialized).unwrap();
+            assert_eq!("{\"semitones\":12.5,\"duration\":4410,\"curve\":{\"Exponential\":[]}}", serialized);
+
+            let deserialized: PitchEnvelope = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
-            assert_eq!(porta_freq, deserialized);
+            assert_eq!(pitch_envelope, deserialized);
         }
     }
 
-    mod dynamic {
-        use note_freq::Dynamic;
+    mod pitch_envelope_offset {
+        use note_freq::PitchEnvelopeOffset;
         use super::super::serde;
 
-        impl serde::Serialize for Dynamic {
+        impl serde::Serialize for PitchEnvelopeOffset {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
-                match *self {
-                    Dynamic::Portamento(p) =>
-                        serializer.serialize_newtype_variant("Dynamic", 0, "Portamento", p),
-                    Dynamic::Constant(hz) =>
-                        serializer.serialize_newtype_variant("Dynamic", 1, "Constant", hz),
-                }
-            }
-        }
-
-        impl serde::Deserialize for Dynamic {
-            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
-                where D: serde::Deserializer,
-            {
-                enum Variant {
-                    Portamento,
-                    Constant,
+                struct Visitor<'a> {
+                    t: &'a PitchEnvelopeOffset,
+                    field_idx: u8,
                 }
 
-                impl serde::de::Deserialize for Variant {
-                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
-                        where D: serde::Deserializer,
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
                     {
-                        struct VariantVisitor;
-
-                        impl serde::de::Visitor for VariantVisitor {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("envelope",
+                                                                             self.t.envelope))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("current_sample",
+                                                                             self.t.current_sample))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("PitchEnvelopeOffset", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for PitchEnvelopeOffset {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = PitchEnvelopeOffset;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<PitchEnvelopeOffset, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut envelope = None;
+                        let mut current_sample = None;
+
+                        enum Field {
+                            Envelope,
+                            CurrentSample,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "envelope" => Ok(Field::Envelope),
+                                            "current_sample" => Ok(Field::CurrentSample),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected envelope or current_sample"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Envelope) => { envelope = Some(try!(visitor.visit_value())); },
+                                Some(Field::CurrentSample) => { current_sample = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let envelope = match envelope {
+                            Some(envelope) => envelope,
+                            None => return Err(serde::de::Error::missing_field("envelope")),
+                        };
+
+                        let current_sample = match current_sample {
+                            Some(current_sample) => current_sample,
+                            None => return Err(serde::de::Error::missing_field("current_sample")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(PitchEnvelopeOffset {
+                            envelope: envelope,
+                            current_sample: current_sample,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "envelope",
+                    "current_sample",
+                ];
+
+                deserializer.deserialize_struct("PitchEnvelopeOffset", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::PitchEnvelope;
+            extern crate serde_json;
+
+            let offset = PitchEnvelopeOffset { envelope: PitchEnvelope::new(12.5, 4410), current_sample: 50 };
+            let serialized = serde_json::to_string(&offset).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"envelope\":{\"semitones\":12.5,\"duration\":4410,\"curve\":{\"Linear\":[]}},\"current_sample\":50}", serialized);
+
+            let deserialized: PitchEnvelopeOffset = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(offset, deserialized);
+        }
+    }
+
+    mod vibrato {
+        use note_freq::Vibrato;
+        use super::super::serde;
+
+        impl serde::Serialize for Vibrato {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Vibrato,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("portamento",
+                                                                             self.t.portamento))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("lfo",
+                                                                             self.t.lfo))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("Vibrato", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Vibrato {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Vibrato;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Vibrato, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut portamento = None;
+                        let mut lfo = None;
+
+                        enum Field {
+                            Portamento,
+                            Lfo,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "portamento" => Ok(Field::Portamento),
+                                            "lfo" => Ok(Field::Lfo),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected portamento or lfo"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Portamento) => { portamento = Some(try!(visitor.visit_value())); },
+                                Some(Field::Lfo) => { lfo = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let portamento = match portamento {
+                            Some(portamento) => portamento,
+                            None => return Err(serde::de::Error::missing_field("portamento")),
+                        };
+
+                        let lfo = match lfo {
+                            Some(lfo) => lfo,
+                            None => return Err(serde::de::Error::missing_field("lfo")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Vibrato {
+                            portamento: portamento,
+                            lfo: lfo,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "portamento",
+                    "lfo",
+                ];
+
+                deserializer.deserialize_struct("Vibrato", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let vibrato = Vibrato::new(100, 25.5).delay(200).fade_in(300);
+            let serialized = serde_json::to_string(&vibrato).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"portamento\":[0,{\"Always\":[]}],\"lfo\":{\"period\":100,\"depth_cents\":25.5,\"delay\":200,\"fade_in\":300}}", serialized);
+
+            let deserialized: Vibrato = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(vibrato, deserialized);
+        }
+    }
+
+    mod vibrato_freq {
+        use note_freq::VibratoFreq;
+        use super::super::serde;
+
+        impl serde::Serialize for VibratoFreq {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a VibratoFreq,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("glide",
+                                                                             self.t.glide))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("lfo",
+                                                                             self.t.lfo))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("VibratoFreq", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for VibratoFreq {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = VibratoFreq;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<VibratoFreq, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut glide = None;
+                        let mut lfo = None;
+
+                        enum Field {
+                            Glide,
+                            Lfo,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "glide" => Ok(Field::Glide),
+                                            "lfo" => Ok(Field::Lfo),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected glide or lfo"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Glide) => { glide = Some(try!(visitor.visit_value())); },
+                                Some(Field::Lfo) => { lfo = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let glide = match glide {
+                            Some(glide) => glide,
+                            None => return Err(serde::de::Error::missing_field("glide")),
+                        };
+
+                        let lfo = match lfo {
+                            Some(lfo) => lfo,
+                            None => return Err(serde::de::Error::missing_field("lfo")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(VibratoFreq {
+                            glide: glide,
+                            lfo: lfo,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "glide",
+                    "lfo",
+                ];
+
+                deserializer.deserialize_struct("VibratoFreq", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::{Lfo, LfoOffset, PortamentoFreq};
+            extern crate serde_json;
+
+            let vibrato_freq = VibratoFreq {
+                glide: PortamentoFreq {
+                    current_sample: 0,
+                    target_samples: 10_000,
+                    start_mel: 10.5,
+                    target_mel: 20.5,
+                },
+                lfo: LfoOffset {
+                    lfo: Lfo::new(100, 25.5).delay(200).fade_in(300),
+                    current_sample: 0,
+                },
+            };
+            let serialized = serde_json::to_string(&vibrato_freq).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"glide\":{\"current_sample\":0,\"target_samples\":10000,\"start_mel\":10.5,\"target_mel\":20.5},\"lfo\":{\"lfo\":{\"period\":100,\"depth_cents\":25.5,\"delay\":200,\"fade_in\":300},\"current_sample\":0}}", serialized);
+
+            let deserialized: VibratoFreq = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(vibrato_freq, deserialized);
+        }
+    }
+
+    mod modified {
+        use note_freq::Modified;
+        use super::super::serde;
+
+        impl<NFG, M> serde::Serialize for Modified<NFG, M>
+            where NFG: serde::Serialize,
+                  M: serde::Serialize,
+        {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a, NFG: 'a, M: 'a> {
+                    t: &'a Modified<NFG, M>,
+                    field_idx: u8,
+                }
+
+                impl<'a, NFG, M> serde::ser::MapVisitor for Visitor<'a, NFG, M>
+                    where NFG: serde::Serialize,
+                          M: serde::Serialize,
+                {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("base",
+                                                                             &self.t.base))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("modifier",
+                                                                             &self.t.modifier))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("Modified", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl<NFG, M> serde::Deserialize for Modified<NFG, M>
+            where NFG: serde::Deserialize,
+                  M: serde::Deserialize,
+        {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                use std;
+
+                struct Visitor<NFG, M> {
+                    base: std::marker::PhantomData<NFG>,
+                    modifier: std::marker::PhantomData<M>,
+                }
+
+                impl<NFG, M> serde::de::Visitor for Visitor<NFG, M>
+                    where NFG: serde::Deserialize,
+                          M: serde::Deserialize,
+                {
+                    type Value = Modified<NFG, M>;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Modified<NFG, M>, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut base = None;
+                        let mut modifier = None;
+
+                        enum Field {
+                            Base,
+                            Modifier,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "base" => Ok(Field::Base),
+                                            "modifier" => Ok(Field::Modifier),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected base or modifier"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Base) => { base = Some(try!(visitor.visit_value())); },
+                                Some(Field::Modifier) => { modifier = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let base = match base {
+                            Some(base) => base,
+                            None => return Err(serde::de::Error::missing_field("base")),
+                        };
+
+                        let modifier = match modifier {
+                            Some(modifier) => modifier,
+                            None => return Err(serde::de::Error::missing_field("modifier")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Modified {
+                            base: base,
+                            modifier: modifier,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "base",
+                    "modifier",
+                ];
+
+                let visitor = Visitor {
+                    base: std::marker::PhantomData,
+                    modifier: std::marker::PhantomData,
+                };
+                deserializer.deserialize_struct("Modified", FIELDS, visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::{Cents, NoteFreqGenerator, Portamento};
+            extern crate serde_json;
+
+            let modified = Portamento::always(100).modify(Cents(100.5));
+            let serialized = serde_json::to_string(&modified).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"base\":[100,{\"Always\":[]}],\"modifier\":100.5}", serialized);
+
+            let deserialized: Modified<Portamento, Cents> = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(modified, deserialized);
+        }
+    }
+
+    mod modified_freq {
+        use note_freq::ModifiedFreq;
+        use super::super::serde;
+
+        impl<NF, O> serde::Serialize for ModifiedFreq<NF, O>
+            where NF: serde::Serialize,
+                  O: serde::Serialize,
+        {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a, NF: 'a, O: 'a> {
+                    t: &'a ModifiedFreq<NF, O>,
+                    field_idx: u8,
+                }
+
+                impl<'a, NF, O> serde::ser::MapVisitor for Visitor<'a, NF, O>
+                    where NF: serde::Serialize,
+                          O: serde::Serialize,
+                {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("base",
+                                                                             &self.t.base))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("offset",
+                                                                             &self.t.offset))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("ModifiedFreq", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl<NF, O> serde::Deserialize for ModifiedFreq<NF, O>
+            where NF: serde::Deserialize,
+                  O: serde::Deserialize,
+        {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                use std;
+
+                struct Visitor<NF, O> {
+                    base: std::marker::PhantomData<NF>,
+                    offset: std::marker::PhantomData<O>,
+                }
+
+                impl<NF, O> serde::de::Visitor for Visitor<NF, O>
+                    where NF: serde::Deserialize,
+                          O: serde::Deserialize,
+                {
+                    type Value = ModifiedFreq<NF, O>;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<ModifiedFreq<NF, O>, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut base = None;
+                        let mut offset = None;
+
+                        enum Field {
+                            Base,
+                            Offset,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "base" => Ok(Field::Base),
+                                            "offset" => Ok(Field::Offset),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected base or offset"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Base) => { base = Some(try!(visitor.visit_value())); },
+                                Some(Field::Offset) => { offset = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let base = match base {
+                            Some(base) => base,
+                            None => return Err(serde::de::Error::missing_field("base")),
+                        };
+
+                        let offset = match offset {
+                            Some(offset) => offset,
+                            None => return Err(serde::de::Error::missing_field("offset")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(ModifiedFreq {
+                            base: base,
+                            offset: offset,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "base",
+                    "offset",
+                ];
+
+                let visitor = Visitor {
+                    base: std::marker::PhantomData,
+                    offset: std::marker::PhantomData,
+                };
+                deserializer.deserialize_struct("ModifiedFreq", FIELDS, visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::Cents;
+            extern crate serde_json;
+
+            let modified_freq = ModifiedFreq { base: 440.5, offset: Cents(100.5) };
+            let serialized = serde_json::to_string(&modified_freq).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"base\":440.5,\"offset\":100.5}", serialized);
+
+            let deserialized: ModifiedFreq<f32, Cents> = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(modified_freq, deserialized);
+        }
+    }
+
+    mod lfo {
+        use note_freq::Lfo;
+        use super::super::serde;
+
+        impl serde::Serialize for Lfo {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Lfo,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("period",
+                                                                             self.t.period))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("depth_cents",
+                                                                             self.t.depth_cents))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("delay",
+                                                                             self.t.delay))))
+                            },
+                            3 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("fade_in",
+                                                                             self.t.fade_in))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(4)
+                    }
+                }
+
+                serializer.serialize_struct("Lfo", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Lfo {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Lfo;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Lfo, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut period = None;
+                        let mut depth_cents = None;
+                        let mut delay = None;
+                        let mut fade_in = None;
+
+                        enum Field {
+                            Period,
+                            DepthCents,
+                            Delay,
+                            FadeIn,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "period" => Ok(Field::Period),
+                                            "depth_cents" => Ok(Field::DepthCents),
+                                            "delay" => Ok(Field::Delay),
+                                            "fade_in" => Ok(Field::FadeIn),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected period, depth_cents, delay or fade_in"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Period) => { period = Some(try!(visitor.visit_value())); },
+                                Some(Field::DepthCents) => { depth_cents = Some(try!(visitor.visit_value())); },
+                                Some(Field::Delay) => { delay = Some(try!(visitor.visit_value())); },
+                                Some(Field::FadeIn) => { fade_in = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let period = match period {
+                            Some(period) => period,
+                            None => return Err(serde::de::Error::missing_field("period")),
+                        };
+
+                        let depth_cents = match depth_cents {
+                            Some(depth_cents) => depth_cents,
+                            None => return Err(serde::de::Error::missing_field("depth_cents")),
+                        };
+
+                        let delay = match delay {
+                            Some(delay) => delay,
+                            None => return Err(serde::de::Error::missing_field("delay")),
+                        };
+
+                        let fade_in = match fade_in {
+                            Some(fade_in) => fade_in,
+                            None => return Err(serde::de::Error::missing_field("fade_in")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Lfo {
+                            period: period,
+                            depth_cents: depth_cents,
+                            delay: delay,
+                            fade_in: fade_in,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "period",
+                    "depth_cents",
+                    "delay",
+                    "fade_in",
+                ];
+
+                deserializer.deserialize_struct("Lfo", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let lfo = Lfo::new(100, 25.5).delay(200).fade_in(300);
+            let serialized = serde_json::to_string(&lfo).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"period\":100,\"depth_cents\":25.5,\"delay\":200,\"fade_in\":300}", serialized);
+
+            let deserialized: Lfo = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(lfo, deserialized);
+        }
+    }
+
+    mod lfo_offset {
+        use note_freq::LfoOffset;
+        use super::super::serde;
+
+        impl serde::Serialize for LfoOffset {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a LfoOffset,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("lfo",
+                                                                             self.t.lfo))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("current_sample",
+                                                                             self.t.current_sample))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("LfoOffset", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for LfoOffset {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = LfoOffset;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<LfoOffset, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut lfo = None;
+                        let mut current_sample = None;
+
+                        enum Field {
+                            Lfo,
+                            CurrentSample,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "lfo" => Ok(Field::Lfo),
+                                            "current_sample" => Ok(Field::CurrentSample),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected lfo or current_sample"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Lfo) => { lfo = Some(try!(visitor.visit_value())); },
+                                Some(Field::CurrentSample) => { current_sample = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let lfo = match lfo {
+                            Some(lfo) => lfo,
+                            None => return Err(serde::de::Error::missing_field("lfo")),
+                        };
+
+                        let current_sample = match current_sample {
+                            Some(current_sample) => current_sample,
+                            None => return Err(serde::de::Error::missing_field("current_sample")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(LfoOffset {
+                            lfo: lfo,
+                            current_sample: current_sample,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "lfo",
+                    "current_sample",
+                ];
+
+                deserializer.deserialize_struct("LfoOffset", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::Lfo;
+            extern crate serde_json;
+
+            let lfo_offset = LfoOffset { lfo: Lfo::new(100, 25.5), current_sample: 50 };
+            let serialized = serde_json::to_string(&lfo_offset).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"lfo\":{\"period\":100,\"depth_cents\":25.5,\"delay\":0,\"fade_in\":0},\"current_sample\":50}", serialized);
+
+            let deserialized: LfoOffset = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(lfo_offset, deserialized);
+        }
+    }
+
+    mod cents {
+        use note_freq::Cents;
+        use super::super::serde;
+
+        impl serde::Serialize for Cents {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                serializer.serialize_newtype_struct("Cents", self.0)
+            }
+        }
+
+        impl serde::Deserialize for Cents {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Cents;
+
+                    fn visit_f64<E>(&mut self, v: f64) -> Result<Self::Value, E>
+                        where E: serde::de::Error,
+                    {
+                        Ok(Cents(v as f32))
+                    }
+
+                    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        Ok(Cents(try!(serde::de::Deserialize::deserialize(deserializer))))
+                    }
+                }
+
+                deserializer.deserialize_newtype_struct("Cents", Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let cents = Cents(100.5);
+            let serialized = serde_json::to_string(&cents).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("100.5", serialized);
+
+            let deserialized: Cents = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(cents, deserialized);
+        }
+    }
+
+    mod drift {
+        use note_freq::Drift;
+        use super::super::serde;
+
+        impl serde::Serialize for Drift {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Drift,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("cents",
+                                                                             self.t.cents))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("period",
+                                                                             self.t.period))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("Drift", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Drift {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Drift;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Drift, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut cents = None;
+                        let mut period = None;
+
+                        enum Field {
+                            Cents,
+                            Period,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "cents" => Ok(Field::Cents),
+                                            "period" => Ok(Field::Period),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected cents or period"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Cents) => { cents = Some(try!(visitor.visit_value())); },
+                                Some(Field::Period) => { period = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let cents = match cents {
+                            Some(cents) => cents,
+                            None => return Err(serde::de::Error::missing_field("cents")),
+                        };
+
+                        let period = match period {
+                            Some(period) => period,
+                            None => return Err(serde::de::Error::missing_field("period")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Drift {
+                            cents: cents,
+                            period: period,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "cents",
+                    "period",
+                ];
+
+                deserializer.deserialize_struct("Drift", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let drift = Drift::new(10.5, 4410);
+            let serialized = serde_json::to_string(&drift).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"cents\":10.5,\"period\":4410}", serialized);
+
+            let deserialized: Drift = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(drift, deserialized);
+        }
+    }
+
+    mod drift_offset {
+        use note_freq::DriftOffset;
+        use super::super::serde;
+
+        impl serde::Serialize for DriftOffset {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a DriftOffset,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("drift",
+                                                                             self.t.drift))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("rng",
+                                                                             self.t.rng))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("from_cents",
+                                                                             self.t.from_cents))))
+                            },
+                            3 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("to_cents",
+                                                                             self.t.to_cents))))
+                            },
+                            4 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("current_sample",
+                                                                             self.t.current_sample))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(5)
+                    }
+                }
+
+                serializer.serialize_struct("DriftOffset", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for DriftOffset {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = DriftOffset;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<DriftOffset, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut drift = None;
+                        let mut rng = None;
+                        let mut from_cents = None;
+                        let mut to_cents = None;
+                        let mut current_sample = None;
+
+                        enum Field {
+                            Drift,
+                            Rng,
+                            FromCents,
+                            ToCents,
+                            CurrentSample,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "drift" => Ok(Field::Drift),
+                                            "rng" => Ok(Field::Rng),
+                                            "from_cents" => Ok(Field::FromCents),
+                                            "to_cents" => Ok(Field::ToCents),
+                                            "current_sample" => Ok(Field::CurrentSample),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected drift, rng, from_cents, to_cents or current_sample"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Drift) => { drift = Some(try!(visitor.visit_value())); },
+                                Some(Field::Rng) => { rng = Some(try!(visitor.visit_value())); },
+                                Some(Field::FromCents) => { from_cents = Some(try!(visitor.visit_value())); },
+                                Some(Field::ToCents) => { to_cents = Some(try!(visitor.visit_value())); },
+                                Some(Field::CurrentSample) => { current_sample = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let drift = match drift {
+                            Some(drift) => drift,
+                            None => return Err(serde::de::Error::missing_field("drift")),
+                        };
+
+                        let rng = match rng {
+                            Some(rng) => rng,
+                            None => return Err(serde::de::Error::missing_field("rng")),
+                        };
+
+                        let from_cents = match from_cents {
+                            Some(from_cents) => from_cents,
+                            None => return Err(serde::de::Error::missing_field("from_cents")),
+                        };
+
+                        let to_cents = match to_cents {
+                            Some(to_cents) => to_cents,
+                            None => return Err(serde::de::Error::missing_field("to_cents")),
+                        };
+
+                        let current_sample = match current_sample {
+                            Some(current_sample) => current_sample,
+                            None => return Err(serde::de::Error::missing_field("current_sample")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(DriftOffset {
+                            drift: drift,
+                            rng: rng,
+                            from_cents: from_cents,
+                            to_cents: to_cents,
+                            current_sample: current_sample,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "drift",
+                    "rng",
+                    "from_cents",
+                    "to_cents",
+                    "current_sample",
+                ];
+
+                deserializer.deserialize_struct("DriftOffset", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::{DetuneRng, Drift};
+            extern crate serde_json;
+
+            let drift_offset = DriftOffset {
+                drift: Drift::new(10.5, 4410),
+                rng: DetuneRng::new(7),
+                from_cents: 1.5,
+                to_cents: -2.5,
+                current_sample: 50,
+            };
+            let serialized = serde_json::to_string(&drift_offset).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"drift\":{\"cents\":10.5,\"period\":4410},\"rng\":[1496452567,1674306020,4097599004,72105175],\"from_cents\":1.5,\"to_cents\":-2.5,\"current_sample\":50}", serialized);
+
+            let deserialized: DriftOffset = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(drift_offset, deserialized);
+        }
+    }
+
+    mod dynamic_generator {
+        use note_freq::DynamicGenerator;
+        use super::super::serde;
+
+        impl serde::Serialize for DynamicGenerator {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    DynamicGenerator::Portamento(p) =>
+                        serializer.serialize_newtype_variant("DynamicGenrator", 0, "Portamento", p),
+                    DynamicGenerator::Constant =>
+                        serializer.serialize_unit_variant("DynamicGenrator", 1, "Constant"),
+                    DynamicGenerator::Vibrato(v) =>
+                        serializer.serialize_newtype_variant("DynamicGenrator", 2, "Vibrato", v),
+                }
+            }
+        }
+
+        impl serde::Deserialize for DynamicGenerator {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Portamento,
+                    Constant,
+                    Vibrato,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Portamento" => Ok(Variant::Portamento),
+                                    "Constant" => Ok(Variant::Constant),
+                                    "Vibrato" => Ok(Variant::Vibrato),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = DynamicGenerator;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Portamento => {
+                                let porta = try!(visitor.visit_newtype());
+                                Ok(DynamicGenerator::Portamento(porta))
+                            },
+                            Variant::Constant => {
+                                try!(visitor.visit_unit());
+                                Ok(DynamicGenerator::Constant)
+                            },
+                            Variant::Vibrato => {
+                                let vibrato = try!(visitor.visit_newtype());
+                                Ok(DynamicGenerator::Vibrato(vibrato))
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Portamento", "Constant", "Vibrato"];
+
+                deserializer.deserialize_enum("DynamicGenerator", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use note_freq::Portamento;
+            extern crate serde_json;
+
+            let porta_freq = DynamicGenerator::Portamento(Portamento::legato(20));
+            let serialized = serde_json::to_string(&porta_freq).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Portamento\":[20,{\"Legato\":[]}]}", serialized);
+            
+            let deserialized: DynamicGenerator = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(porta_freq, deserialized);
+        }
+    }
+
+    mod dynamic {
+        use note_freq::Dynamic;
+        use super::super::serde;
+
+        impl serde::Serialize for Dynamic {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Dynamic::Portamento(p) =>
+                        serializer.serialize_newtype_variant("Dynamic", 0, "Portamento", p),
+                    Dynamic::Constant(hz) =>
+                        serializer.serialize_newtype_variant("Dynamic", 1, "Constant", hz),
+                    Dynamic::Vibrato(v) =>
+                        serializer.serialize_newtype_variant("Dynamic", 2, "Vibrato", v),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Dynamic {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Portamento,
+                    Constant,
+                    Vibrato,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Portamento" => Ok(Variant::Portamento),
+                                    "Constant" => Ok(Variant::Constant),
+                                    "Vibrato" => Ok(Variant::Vibrato),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Dynamic;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Portamento => {
+                                let porta = try!(visitor.visit_newtype());
+                                Ok(Dynamic::Portamento(porta))
+                            },
+                            Variant::Constant => {
+                                let hz = try!(visitor.visit_newtype());
+                                Ok(Dynamic::Constant(hz))
+                            },
+                            Variant::Vibrato => {
+                                let vibrato = try!(visitor.visit_newtype());
+                                Ok(Dynamic::Vibrato(vibrato))
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Portamento", "Constant", "Vibrato"];
+
+                deserializer.deserialize_enum("Dynamic", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let hz = Dynamic::Constant(440.0);
+            let serialized = serde_json::to_string(&hz).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Constant\":440.0}", serialized);
+            
+            let deserialized: Dynamic = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(hz, deserialized);
+        }
+    }
+
+    mod detune_rng {
+        use note_freq::DetuneRng;
+        use super::super::serde;
+
+        impl serde::Serialize for DetuneRng {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                serializer.serialize_newtype_struct("DetuneRng", self.state)
+            }
+        }
+
+        impl serde::Deserialize for DetuneRng {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = DetuneRng;
+
+                    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        let state: [u32; 4] = try!(serde::de::Deserialize::deserialize(deserializer));
+                        if state == [0; 4] {
+                            return Err(serde::de::Error::custom("expected a non-zero DetuneRng state"));
+                        }
+                        Ok(DetuneRng { state: state })
+                    }
+                }
+
+                deserializer.deserialize_newtype_struct("DetuneRng", Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use rand::Rng;
+            extern crate serde_json;
+
+            let mut rng = DetuneRng::new(7);
+            rng.next_u32();
+            let serialized = serde_json::to_string(&rng).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!(format!("{:?}", rng.state).replace(" ", ""), serialized);
+
+            let mut deserialized: DetuneRng = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(rng, deserialized);
+            assert_eq!(rng.next_u32(), deserialized.next_u32());
+
+            assert!(serde_json::from_str::<DetuneRng>("[0,0,0,0]").is_err());
+        }
+    }
+}
+
+mod mode {
+
+    mod mono_kind {
+        use mode::MonoKind;
+        use super::super::serde;
+
+        impl serde::Serialize for MonoKind {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    MonoKind::Retrigger => serializer.serialize_unit_variant("MonoKind", 0, "Retrigger"),
+                    MonoKind::Legato    => serializer.serialize_unit_variant("MonoKind", 1, "Legato"),
+                }
+            }
+        }
+
+        impl serde::Deserialize for MonoKind {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Retrigger,
+                    Legato,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_usize<E>(&mut self, value: usize) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                let var = match value {
+                                    0 => Variant::Retrigger,
+                                    1 => Variant::Legato,
+                                    _ => return Err(serde::de::Error::unknown_field(&value.to_string())),
+                                };
+                                Ok(var)
+                            }
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Retrigger" => Ok(Variant::Retrigger),
+                                    "Legato"    => Ok(Variant::Legato),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = MonoKind;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        let kind = match try!(visitor.visit_variant()) {
+                            Variant::Retrigger => MonoKind::Retrigger,
+                            Variant::Legato => MonoKind::Legato,
+                        };
+                        try!(visitor.visit_unit());
+                        Ok(kind)
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Retrigger", "Legato"];
+
+                deserializer.deserialize_enum("MonoKind", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let kind = MonoKind::Retrigger;
+            let serialized = serde_json::to_string(&kind).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Retrigger\":[]}", &serialized);
+            
+            let deserialized: MonoKind = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(kind, deserialized);
+        }
+
+    }
+
+    mod mono {
+        use mode::{Mono, Priority, Unison};
+        use super::super::serde;
+        use unit::{NoteHz, NoteId};
+
+        /// A note on the stack of a `Mono`, which was once serialized as its frequency alone.
+        struct StackNote(NoteHz, Option<NoteId>);
+
+        impl serde::Deserialize for StackNote {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = StackNote;
+
+                    fn visit_f64<E>(&mut self, v: f64) -> Result<Self::Value, E>
+                        where E: serde::de::Error,
+                    {
+                        Ok(StackNote(v as NoteHz, None))
+                    }
+
+                    fn visit_i64<E>(&mut self, v: i64) -> Result<Self::Value, E>
+                        where E: serde::de::Error,
+                    {
+                        Ok(StackNote(v as NoteHz, None))
+                    }
+
+                    fn visit_u64<E>(&mut self, v: u64) -> Result<Self::Value, E>
+                        where E: serde::de::Error,
+                    {
+                        Ok(StackNote(v as NoteHz, None))
+                    }
+
+                    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<StackNote, V::Error>
+                        where V: serde::de::SeqVisitor,
+                    {
+                        let hz = try!(visitor.visit());
+                        let id = try!(visitor.visit());
+
+                        let hz = match hz {
+                            Some(hz) => hz,
+                            None => return Err(serde::de::Error::missing_field("hz")),
+                        };
+
+                        let id = match id {
+                            Some(id) => id,
+                            None => return Err(serde::de::Error::missing_field("id")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(StackNote(hz, id))
+                    }
+                }
+
+                deserializer.deserialize(Visitor)
+            }
+        }
+
+        impl serde::Serialize for Mono {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Mono,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::SeqVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.0))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(&self.t.1))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.2))))
+                            },
+                            3 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.3))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(4)
+                    }
+                }
+
+                serializer.serialize_tuple_struct("Mono", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Mono {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Mono;
+
+                    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Mono, V::Error>
+                        where V: serde::de::SeqVisitor,
+                    {
+                        let kind = try!(visitor.visit());
+                        let notes = try!(visitor.visit());
+                        let unison = try!(visitor.visit());
+                        let priority = try!(visitor.visit());
+
+                        let kind = match kind {
+                            Some(kind) => kind,
+                            None => return Err(serde::de::Error::missing_field("kind")),
+                        };
+
+                        let notes: Vec<StackNote> = match notes {
+                            Some(notes) => notes,
+                            None => return Err(serde::de::Error::missing_field("notes")),
+                        };
+                        let notes = notes.into_iter().map(|StackNote(hz, id)| (hz, id)).collect();
+
+                        // A `Mono` serialized before it had a `Unison` and `Priority` uses the
+                        // defaults.
+                        let unison = unison.unwrap_or_else(Unison::default);
+                        let priority = priority.unwrap_or_else(Priority::default);
+
+                        try!(visitor.end());
+
+                        Ok(Mono(kind, notes, unison, priority))
+                    }
+                }
+
+                deserializer.deserialize_tuple_struct("Mono", 4, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use mode::MonoKind;
+            extern crate serde_json;
+
+            let notes = vec![(440.0, None), (220.0, Some(57))];
+            let mono = Mono(MonoKind::Retrigger, notes, Unison::new(12.5, 0.5), Priority::Low);
+            let serialized = serde_json::to_string(&mono).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("[{\"Retrigger\":[]},[[440.0,null],[220.0,57]],{\"cents\":12.5,\"pan\":0.5},{\"Low\":[]}]", &serialized);
+            
+            let deserialized: Mono = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(mono, deserialized);
+
+            let old: Mono = serde_json::from_str("[{\"Retrigger\":[]},[440.0,220]]").unwrap();
+            let notes = vec![(440.0, None), (220.0, None)];
+            assert_eq!(Mono(MonoKind::Retrigger, notes, Unison::default(), Priority::default()), old);
+        }
+
+    }
+
+    mod priority {
+        use mode::Priority;
+        use super::super::serde;
+
+        impl serde::Serialize for Priority {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Priority::Last =>
+                        serializer.serialize_unit_variant("Priority", 0, "Last"),
+                    Priority::Low =>
+                        serializer.serialize_unit_variant("Priority", 1, "Low"),
+                    Priority::High =>
+                        serializer.serialize_unit_variant("Priority", 2, "High"),
+                    Priority::First =>
+                        serializer.serialize_unit_variant("Priority", 3, "First"),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Priority {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Last,
+                    Low,
+                    High,
+                    First,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Last" => Ok(Variant::Last),
+                                    "Low" => Ok(Variant::Low),
+                                    "High" => Ok(Variant::High),
+                                    "First" => Ok(Variant::First),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Priority;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Last => {
+                                try!(visitor.visit_unit());
+                                Ok(Priority::Last)
+                            },
+                            Variant::Low => {
+                                try!(visitor.visit_unit());
+                                Ok(Priority::Low)
+                            },
+                            Variant::High => {
+                                try!(visitor.visit_unit());
+                                Ok(Priority::High)
+                            },
+                            Variant::First => {
+                                try!(visitor.visit_unit());
+                                Ok(Priority::First)
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Last", "Low", "High", "First"];
+
+                deserializer.deserialize_enum("Priority", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let priority = Priority::High;
+            let serialized = serde_json::to_string(&priority).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"High\":[]}", serialized);
+
+            let deserialized: Priority = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(priority, deserialized);
+        }
+
+    }
+
+    mod unison {
+        use mode::Unison;
+        use super::super::serde;
+
+        impl serde::Serialize for Unison {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Unison,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("cents",
+                                                                             self.t.cents))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("pan",
+                                                                             self.t.pan))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("Unison", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Unison {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Unison;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Unison, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut cents = None;
+                        let mut pan = None;
+
+                        enum Field {
+                            Cents,
+                            Pan,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "cents" => Ok(Field::Cents),
+                                            "pan" => Ok(Field::Pan),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected cents or pan"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Cents) => { cents = Some(try!(visitor.visit_value())); },
+                                Some(Field::Pan) => { pan = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let cents = match cents {
+                            Some(cents) => cents,
+                            None => return Err(serde::de::Error::missing_field("cents")),
+                        };
+
+                        let pan = match pan {
+                            Some(pan) => pan,
+                            None => return Err(serde::de::Error::missing_field("pan")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Unison {
+                            cents: cents,
+                            pan: pan,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "cents",
+                    "pan",
+                ];
+
+                deserializer.deserialize_struct("Unison", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let unison = Unison::new(12.5, 0.5);
+            let serialized = serde_json::to_string(&unison).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"cents\":12.5,\"pan\":0.5}", serialized);
+
+            let deserialized: Unison = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(unison, deserialized);
+        }
+    }
+
+    mod poly {
+        use mode::{Poly, Steal};
+        use super::super::serde;
+
+        impl serde::Serialize for Poly {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                serializer.serialize_newtype_struct("Poly", self.0)
+            }
+        }
+
+        impl serde::Deserialize for Poly {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Poly;
+
+                    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        // `Poly` was once a unit struct, in which case the default policy is used.
+                        let steal: Option<Steal> = try!(serde::de::Deserialize::deserialize(deserializer));
+                        Ok(steal.map_or_else(Poly::default, Poly))
+                    }
+                }
+
+                deserializer.deserialize_newtype_struct("Poly", Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let poly = Poly(Steal::Quietest);
+            let serialized = serde_json::to_string(&poly).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Quietest\":[]}", &serialized);
+            
+            let deserialized: Poly = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(poly, deserialized);
+
+            let unit: Poly = serde_json::from_str("null").unwrap();
+            assert_eq!(Poly::default(), unit);
+        }
+    }
+
+    mod steal {
+        use mode::Steal;
+        use super::super::serde;
+
+        impl serde::Serialize for Steal {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Steal::Oldest =>
+                        serializer.serialize_unit_variant("Steal", 0, "Oldest"),
+                    Steal::Quietest =>
+                        serializer.serialize_unit_variant("Steal", 1, "Quietest"),
+                    Steal::Released =>
+                        serializer.serialize_unit_variant("Steal", 2, "Released"),
+                    Steal::Lowest =>
+                        serializer.serialize_unit_variant("Steal", 3, "Lowest"),
+                    Steal::Highest =>
+                        serializer.serialize_unit_variant("Steal", 4, "Highest"),
+                    Steal::SameNote =>
+                        serializer.serialize_unit_variant("Steal", 5, "SameNote"),
+                    Steal::Never =>
+                        serializer.serialize_unit_variant("Steal", 6, "Never"),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Steal {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Oldest,
+                    Quietest,
+                    Released,
+                    Lowest,
+                    Highest,
+                    SameNote,
+                    Never,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Oldest" => Ok(Variant::Oldest),
+                                    "Quietest" => Ok(Variant::Quietest),
+                                    "Released" => Ok(Variant::Released),
+                                    "Lowest" => Ok(Variant::Lowest),
+                                    "Highest" => Ok(Variant::Highest),
+                                    "SameNote" => Ok(Variant::SameNote),
+                                    "Never" => Ok(Variant::Never),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Steal;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Oldest => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Oldest)
+                            },
+                            Variant::Quietest => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Quietest)
+                            },
+                            Variant::Released => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Released)
+                            },
+                            Variant::Lowest => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Lowest)
+                            },
+                            Variant::Highest => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Highest)
+                            },
+                            Variant::SameNote => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::SameNote)
+                            },
+                            Variant::Never => {
+                                try!(visitor.visit_unit());
+                                Ok(Steal::Never)
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &[
+                    "Oldest",
+                    "Quietest",
+                    "Released",
+                    "Lowest",
+                    "Highest",
+                    "SameNote",
+                    "Never",
+                ];
+
+                deserializer.deserialize_enum("Steal", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let steal = Steal::SameNote;
+            let serialized = serde_json::to_string(&steal).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"SameNote\":[]}", serialized);
+
+            let deserialized: Steal = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(steal, deserialized);
+        }
+    }
+
+    mod note_match {
+        use mode::NoteMatch;
+        use super::super::serde;
+
+        impl serde::Serialize for NoteMatch {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    NoteMatch::Cents(cents) =>
+                        serializer.serialize_newtype_variant("NoteMatch", 0, "Cents", cents),
+                    NoteMatch::Custom(_) =>
+                        Err(serde::ser::Error::custom("a Custom NoteMatch cannot be serialized")),
+                }
+            }
+        }
+
+        impl serde::Deserialize for NoteMatch {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Cents,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Cents" => Ok(Variant::Cents),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = NoteMatch;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Cents => {
+                                let cents = try!(visitor.visit_newtype());
+                                Ok(NoteMatch::Cents(cents))
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Cents"];
+
+                deserializer.deserialize_enum("NoteMatch", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let note_match = NoteMatch::Cents(2.5);
+            let serialized = serde_json::to_string(&note_match).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Cents\":2.5}", serialized);
+
+            let deserialized: NoteMatch = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(note_match, deserialized);
+
+            fn exact(a: f32, b: f32) -> bool { a == b }
+            assert!(serde_json::to_string(&NoteMatch::Custom(exact)).is_err());
+            assert!(serde_json::from_str::<NoteMatch>("{\"Custom\":[]}").is_err());
+        }
+    }
+
+    mod dynamic {
+        use mode::Dynamic;
+        use super::super::serde;
+
+        impl serde::Serialize for Dynamic {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Dynamic::Mono(ref m) =>
+                        serializer.serialize_newtype_variant("Dynamic", 0, "Mono", m),
+                    Dynamic::Poly(ref p) =>
+                        serializer.serialize_newtype_variant("Dynamic", 1, "Poly", p),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Dynamic {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant { Mono, Poly }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Mono" => Ok(Variant::Mono),
+                                    "Poly" => Ok(Variant::Poly),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Dynamic;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Mono => {
+                                let mono = try!(visitor.visit_newtype());
+                                Ok(Dynamic::Mono(mono))
+                            },
+                            Variant::Poly => {
+                                let poly = try!(visitor.visit_newtype());
+                                Ok(Dynamic::Poly(poly))
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Mono", "Poly"];
+
+                deserializer.deserialize_enum("Dynamic", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use mode::{Poly, Steal};
+            extern crate serde_json;
+
+            let poly = Dynamic::Poly(Poly(Steal::Oldest));
+            let serialized = serde_json::to_string(&poly).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Poly\":{\"Oldest\":[]}}", serialized);
+            
+            let deserialized: Dynamic = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(poly, deserialized);
+        }
+    }
+}
+
+mod envelope {
+
+    mod adsr {
+        use envelope::Adsr;
+        use super::super::serde;
+
+        impl serde::Serialize for Adsr {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a Adsr,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("attack_ms",
+                                                                             self.t.attack_ms))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("attack_curve",
+                                                                             self.t.attack_curve))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("decay_ms",
+                                                                             self.t.decay_ms))))
+                            },
+                            3 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("decay_curve",
+                                                                             self.t.decay_curve))))
+                            },
+                            4 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("sustain",
+                                                                             self.t.sustain))))
+                            },
+                            5 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("release_ms",
+                                                                             self.t.release_ms))))
+                            },
+                            6 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("release_curve",
+                                                                             self.t.release_curve))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(7)
+                    }
+                }
+
+                serializer.serialize_struct("Adsr", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for Adsr {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = Adsr;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Adsr, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut attack_ms = None;
+                        let mut attack_curve = None;
+                        let mut decay_ms = None;
+                        let mut decay_curve = None;
+                        let mut sustain = None;
+                        let mut release_ms = None;
+                        let mut release_curve = None;
+
+                        enum Field {
+                            AttackMs,
+                            AttackCurve,
+                            DecayMs,
+                            DecayCurve,
+                            Sustain,
+                            ReleaseMs,
+                            ReleaseCurve,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "attack_ms" => Ok(Field::AttackMs),
+                                            "attack_curve" => Ok(Field::AttackCurve),
+                                            "decay_ms" => Ok(Field::DecayMs),
+                                            "decay_curve" => Ok(Field::DecayCurve),
+                                            "sustain" => Ok(Field::Sustain),
+                                            "release_ms" => Ok(Field::ReleaseMs),
+                                            "release_curve" => Ok(Field::ReleaseCurve),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected attack_ms, attack_curve, decay_ms, decay_curve, \
+                                                sustain, release_ms or release_curve"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::AttackMs) => { attack_ms = Some(try!(visitor.visit_value())); },
+                                Some(Field::AttackCurve) => { attack_curve = Some(try!(visitor.visit_value())); },
+                                Some(Field::DecayMs) => { decay_ms = Some(try!(visitor.visit_value())); },
+                                Some(Field::DecayCurve) => { decay_curve = Some(try!(visitor.visit_value())); },
+                                Some(Field::Sustain) => { sustain = Some(try!(visitor.visit_value())); },
+                                Some(Field::ReleaseMs) => { release_ms = Some(try!(visitor.visit_value())); },
+                                Some(Field::ReleaseCurve) => { release_curve = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let attack_ms = match attack_ms {
+                            Some(attack_ms) => attack_ms,
+                            None => return Err(serde::de::Error::missing_field("attack_ms")),
+                        };
+
+                        let attack_curve = match attack_curve {
+                            Some(attack_curve) => attack_curve,
+                            None => return Err(serde::de::Error::missing_field("attack_curve")),
+                        };
+
+                        let decay_ms = match decay_ms {
+                            Some(decay_ms) => decay_ms,
+                            None => return Err(serde::de::Error::missing_field("decay_ms")),
+                        };
+
+                        let decay_curve = match decay_curve {
+                            Some(decay_curve) => decay_curve,
+                            None => return Err(serde::de::Error::missing_field("decay_curve")),
+                        };
+
+                        let sustain = match sustain {
+                            Some(sustain) => sustain,
+                            None => return Err(serde::de::Error::missing_field("sustain")),
+                        };
+
+                        let release_ms = match release_ms {
+                            Some(release_ms) => release_ms,
+                            None => return Err(serde::de::Error::missing_field("release_ms")),
+                        };
+
+                        let release_curve = match release_curve {
+                            Some(release_curve) => release_curve,
+                            None => return Err(serde::de::Error::missing_field("release_curve")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(Adsr {
+                            attack_ms: attack_ms,
+                            attack_curve: attack_curve,
+                            decay_ms: decay_ms,
+                            decay_curve: decay_curve,
+                            sustain: sustain,
+                            release_ms: release_ms,
+                            release_curve: release_curve,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "attack_ms",
+                    "attack_curve",
+                    "decay_ms",
+                    "decay_curve",
+                    "sustain",
+                    "release_ms",
+                    "release_curve",
+                ];
+
+                deserializer.deserialize_struct("Adsr", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            use envelope::Curve;
+            extern crate serde_json;
+
+            let adsr = Adsr::new(10.0, (50.0, Curve::Exponential), 0.5, (100.0, Curve::Custom(2.0)));
+            let serialized = serde_json::to_string(&adsr).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"attack_ms\":10.0,\"attack_curve\":{\"Linear\":[]},\"decay_ms\":50.0,\"decay_curve\":{\"Exponential\":[]},\"sustain\":0.5,\"release_ms\":100.0,\"release_curve\":{\"Custom\":2.0}}", serialized);
+
+            let deserialized: Adsr = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(adsr, deserialized);
+        }
+    }
+
+    mod curve {
+        use envelope::Curve;
+        use super::super::serde;
+
+        impl serde::Serialize for Curve {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Curve::Linear =>
+                        serializer.serialize_unit_variant("Curve", 0, "Linear"),
+                    Curve::Exponential =>
+                        serializer.serialize_unit_variant("Curve", 1, "Exponential"),
+                    Curve::Logarithmic =>
+                        serializer.serialize_unit_variant("Curve", 2, "Logarithmic"),
+                    Curve::SCurve =>
+                        serializer.serialize_unit_variant("Curve", 3, "SCurve"),
+                    Curve::Custom(curvature) =>
+                        serializer.serialize_newtype_variant("Curve", 4, "Custom", curvature),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Curve {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Linear,
+                    Exponential,
+                    Logarithmic,
+                    SCurve,
+                    Custom,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
+                            type Value = Variant;
+
+                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                                where E: serde::de::Error,
+                            {
+                                match value {
+                                    "Linear" => Ok(Variant::Linear),
+                                    "Exponential" => Ok(Variant::Exponential),
+                                    "Logarithmic" => Ok(Variant::Logarithmic),
+                                    "SCurve" => Ok(Variant::SCurve),
+                                    "Custom" => Ok(Variant::Custom),
+                                    _ => Err(serde::de::Error::unknown_field(value)),
+                                }
+                            }
+                        }
+
+                        deserializer.deserialize(VariantVisitor)
+                    }
+                }
+
+                struct Visitor;
+
+                impl serde::de::EnumVisitor for Visitor {
+                    type Value = Curve;
+
+                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                        where V: serde::de::VariantVisitor,
+                    {
+                        match try!(visitor.visit_variant()) {
+                            Variant::Linear => {
+                                try!(visitor.visit_unit());
+                                Ok(Curve::Linear)
+                            },
+                            Variant::Exponential => {
+                                try!(visitor.visit_unit());
+                                Ok(Curve::Exponential)
+                            },
+                            Variant::Logarithmic => {
+                                try!(visitor.visit_unit());
+                                Ok(Curve::Logarithmic)
+                            },
+                            Variant::SCurve => {
+                                try!(visitor.visit_unit());
+                                Ok(Curve::SCurve)
+                            },
+                            Variant::Custom => {
+                                let v = try!(visitor.visit_newtype());
+                                Ok(Curve::Custom(v))
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &[
+                    "Linear",
+                    "Exponential",
+                    "Logarithmic",
+                    "SCurve",
+                    "Custom",
+                ];
+
+                deserializer.deserialize_enum("Curve", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let curve = Curve::Custom(-2.5);
+            let serialized = serde_json::to_string(&curve).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"Custom\":-2.5}", serialized);
+
+            let deserialized: Curve = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(curve, deserialized);
+        }
+    }
+}
+
+mod pan {
+
+    mod law {
+        use pan::Law;
+        use super::super::serde;
+
+        impl serde::Serialize for Law {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                match *self {
+                    Law::Linear =>
+                        serializer.serialize_unit_variant("Law", 0, "Linear"),
+                    Law::ConstantPower =>
+                        serializer.serialize_unit_variant("Law", 1, "ConstantPower"),
+                }
+            }
+        }
+
+        impl serde::Deserialize for Law {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                enum Variant {
+                    Linear,
+                    ConstantPower,
+                }
+
+                impl serde::de::Deserialize for Variant {
+                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                        where D: serde::Deserializer,
+                    {
+                        struct VariantVisitor;
+
+                        impl serde::de::Visitor for VariantVisitor {
                             type Value = Variant;
 
                             fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
                                 where E: serde::de::Error,
                             {
                                 match value {
-                                    "Portamento" => Ok(Variant::Portamento),
-                                    "Constant" => Ok(Variant::Constant),
+                                    "Linear" => Ok(Variant::Linear),
+                                    "ConstantPower" => Ok(Variant::ConstantPower),
                                     _ => Err(serde::de::Error::unknown_field(value)),
                                 }
                             }
@@ -384,72 +3575,367 @@ mod note_freq {
                 struct Visitor;
 
                 impl serde::de::EnumVisitor for Visitor {
-                    type Value = Dynamic;
+                    type Value = Law;
 
                     fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
                         where V: serde::de::VariantVisitor,
                     {
                         match try!(visitor.visit_variant()) {
-                            Variant::Portamento => {
-                                let porta = try!(visitor.visit_newtype());
-                                Ok(Dynamic::Portamento(porta))
+                            Variant::Linear => {
+                                try!(visitor.visit_unit());
+                                Ok(Law::Linear)
                             },
-                            Variant::Constant => {
-                                let hz = try!(visitor.visit_newtype());
-                                Ok(Dynamic::Constant(hz))
+                            Variant::ConstantPower => {
+                                try!(visitor.visit_unit());
+                                Ok(Law::ConstantPower)
+                            },
+                        }
+                    }
+                }
+
+                const VARIANTS: &'static [&'static str] = &["Linear", "ConstantPower"];
+
+                deserializer.deserialize_enum("Law", VARIANTS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let law = Law::ConstantPower;
+            let serialized = serde_json::to_string(&law).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"ConstantPower\":[]}", serialized);
+
+            let deserialized: Law = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(law, deserialized);
+        }
+    }
+
+    mod key_track {
+        use pan::KeyTrack;
+        use super::super::serde;
+
+        impl serde::Serialize for KeyTrack {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a KeyTrack,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("center",
+                                                                             self.t.center))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("semitones",
+                                                                             self.t.semitones))))
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("KeyTrack", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for KeyTrack {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = KeyTrack;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<KeyTrack, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut center = None;
+                        let mut semitones = None;
+
+                        enum Field {
+                            Center,
+                            Semitones,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "center" => Ok(Field::Center),
+                                            "semitones" => Ok(Field::Semitones),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected center or semitones"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Center) => { center = Some(try!(visitor.visit_value())); },
+                                Some(Field::Semitones) => { semitones = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
+
+                        let center = match center {
+                            Some(center) => center,
+                            None => return Err(serde::de::Error::missing_field("center")),
+                        };
+
+                        let semitones = match semitones {
+                            Some(semitones) => semitones,
+                            None => return Err(serde::de::Error::missing_field("semitones")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(KeyTrack {
+                            center: center,
+                            semitones: semitones,
+                        })
+                    }
+                }
+
+                static FIELDS: &'static [&'static str] = &[
+                    "center",
+                    "semitones",
+                ];
+
+                deserializer.deserialize_struct("KeyTrack", FIELDS, Visitor)
+            }
+        }
+
+        #[test]
+        fn test() {
+            extern crate serde_json;
+
+            let key_track = KeyTrack::new(60.5, 24.5);
+            let serialized = serde_json::to_string(&key_track).unwrap();
+
+            println!("{}", serialized);
+            assert_eq!("{\"center\":60.5,\"semitones\":24.5}", serialized);
+
+            let deserialized: KeyTrack = serde_json::from_str(&serialized).unwrap();
+
+            println!("{:?}", deserialized);
+            assert_eq!(key_track, deserialized);
+        }
+    }
+
+}
+
+mod bend {
+
+    mod pitch_bend {
+        use bend::PitchBend;
+        use super::super::serde;
+
+        impl serde::Serialize for PitchBend {
+            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+                where S: serde::Serializer,
+            {
+                struct Visitor<'a> {
+                    t: &'a PitchBend,
+                    field_idx: u8,
+                }
+
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                        where S: serde::Serializer,
+                    {
+                        match self.field_idx {
+                            0 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("range",
+                                                                             self.t.range))))
+                            },
+                            1 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("smoothing_ms",
+                                                                             self.t.smoothing_ms))))
                             },
+                            _ => Ok(None),
+                        }
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        Some(2)
+                    }
+                }
+
+                serializer.serialize_struct("PitchBend", Visitor { t: self, field_idx: 0 })
+            }
+        }
+
+        impl serde::Deserialize for PitchBend {
+            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+                where D: serde::Deserializer,
+            {
+                struct Visitor;
+
+                impl serde::de::Visitor for Visitor {
+                    type Value = PitchBend;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<PitchBend, V::Error>
+                        where V: serde::de::MapVisitor,
+                    {
+                        let mut range = None;
+                        let mut smoothing_ms = None;
+
+                        enum Field {
+                            Range,
+                            SmoothingMs,
+                        }
+
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
+
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
+
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "range" => Ok(Field::Range),
+                                            "smoothing_ms" => Ok(Field::SmoothingMs),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected range or smoothing_ms"
+                                            )),
+                                        }
+                                    }
+                                }
+
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
+
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Range) => { range = Some(try!(visitor.visit_value())); },
+                                Some(Field::SmoothingMs) => { smoothing_ms = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
                         }
+
+                        let range = match range {
+                            Some(range) => range,
+                            None => return Err(serde::de::Error::missing_field("range")),
+                        };
+
+                        let smoothing_ms = match smoothing_ms {
+                            Some(smoothing_ms) => smoothing_ms,
+                            None => return Err(serde::de::Error::missing_field("smoothing_ms")),
+                        };
+
+                        try!(visitor.end());
+
+                        Ok(PitchBend {
+                            range: range,
+                            smoothing_ms: smoothing_ms,
+                            target: 0.0,
+                            current: 0.0,
+                        })
                     }
                 }
 
-                const VARIANTS: &'static [&'static str] = &["Portamento", "Constant"];
+                static FIELDS: &'static [&'static str] = &[
+                    "range",
+                    "smoothing_ms",
+                ];
 
-                deserializer.deserialize_enum("Dynamic", VARIANTS, Visitor)
+                deserializer.deserialize_struct("PitchBend", FIELDS, Visitor)
             }
         }
 
         #[test]
         fn test() {
+            use time;
             extern crate serde_json;
 
-            let hz = Dynamic::Constant(440.0);
-            let serialized = serde_json::to_string(&hz).unwrap();
+            let pitch_bend = PitchBend {
+                range: 12.5,
+                smoothing_ms: time::Ms(5.5),
+                target: 0.0,
+                current: 0.0,
+            };
+            let serialized = serde_json::to_string(&pitch_bend).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("{\"Constant\":440}", serialized);
-            
-            let deserialized: Dynamic = serde_json::from_str(&serialized).unwrap();
+            assert_eq!("{\"range\":12.5,\"smoothing_ms\":5.5}", serialized);
+
+            let deserialized: PitchBend = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
-            assert_eq!(hz, deserialized);
+            assert_eq!(pitch_bend, deserialized);
         }
     }
+
 }
 
-mod mode {
+mod mpe {
 
-    mod mono_kind {
-        use mode::MonoKind;
+    mod side {
+        use mpe::Side;
         use super::super::serde;
 
-        impl serde::Serialize for MonoKind {
+        impl serde::Serialize for Side {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
                 match *self {
-                    MonoKind::Retrigger => serializer.serialize_unit_variant("MonoKind", 0, "Retrigger"),
-                    MonoKind::Legato    => serializer.serialize_unit_variant("MonoKind", 1, "Legato"),
+                    Side::Lower =>
+                        serializer.serialize_unit_variant("Side", 0, "Lower"),
+                    Side::Upper =>
+                        serializer.serialize_unit_variant("Side", 1, "Upper"),
                 }
             }
         }
 
-        impl serde::Deserialize for MonoKind {
+        impl serde::Deserialize for Side {
             fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
                 where D: serde::Deserializer,
             {
                 enum Variant {
-                    Retrigger,
-                    Legato,
+                    Lower,
+                    Upper,
                 }
 
                 impl serde::de::Deserialize for Variant {
@@ -461,23 +3947,12 @@ mod mode {
                         impl serde::de::Visitor for VariantVisitor {
                             type Value = Variant;
 
-                            fn visit_usize<E>(&mut self, value: usize) -> Result<Variant, E>
-                                where E: serde::de::Error,
-                            {
-                                let var = match value {
-                                    0 => Variant::Retrigger,
-                                    1 => Variant::Legato,
-                                    _ => return Err(serde::de::Error::unknown_field(&value.to_string())),
-                                };
-                                Ok(var)
-                            }
-
                             fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
                                 where E: serde::de::Error,
                             {
                                 match value {
-                                    "Retrigger" => Ok(Variant::Retrigger),
-                                    "Legato"    => Ok(Variant::Legato),
+                                    "Lower" => Ok(Variant::Lower),
+                                    "Upper" => Ok(Variant::Upper),
                                     _ => Err(serde::de::Error::unknown_field(value)),
                                 }
                             }
@@ -490,23 +3965,27 @@ mod mode {
                 struct Visitor;
 
                 impl serde::de::EnumVisitor for Visitor {
-                    type Value = MonoKind;
+                    type Value = Side;
 
                     fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
                         where V: serde::de::VariantVisitor,
                     {
-                        let kind = match try!(visitor.visit_variant()) {
-                            Variant::Retrigger => MonoKind::Retrigger,
-                            Variant::Legato => MonoKind::Legato,
-                        };
-                        try!(visitor.visit_unit());
-                        Ok(kind)
+                        match try!(visitor.visit_variant()) {
+                            Variant::Lower => {
+                                try!(visitor.visit_unit());
+                                Ok(Side::Lower)
+                            },
+                            Variant::Upper => {
+                                try!(visitor.visit_unit());
+                                Ok(Side::Upper)
+                            },
+                        }
                     }
                 }
 
-                const VARIANTS: &'static [&'static str] = &["Retrigger", "Legato"];
+                const VARIANTS: &'static [&'static str] = &["Lower", "Upper"];
 
-                deserializer.deserialize_enum("MonoKind", VARIANTS, Visitor)
+                deserializer.deserialize_enum("Side", VARIANTS, Visitor)
             }
         }
 
@@ -514,142 +3993,155 @@ mod mode {
         fn test() {
             extern crate serde_json;
 
-            let kind = MonoKind::Retrigger;
-            let serialized = serde_json::to_string(&kind).unwrap();
+            let side = Side::Upper;
+            let serialized = serde_json::to_string(&side).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("{\"Retrigger\":[]}", &serialized);
-            
-            let deserialized: MonoKind = serde_json::from_str(&serialized).unwrap();
+            assert_eq!("{\"Upper\":[]}", serialized);
+
+            let deserialized: Side = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
-            assert_eq!(kind, deserialized);
+            assert_eq!(side, deserialized);
         }
-
     }
 
-    mod mono {
-        use mode::Mono;
+    mod zone {
+        use mpe::Zone;
         use super::super::serde;
 
-        impl serde::Serialize for Mono {
+        impl serde::Serialize for Zone {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
                 struct Visitor<'a> {
-                    t: &'a Mono,
+                    t: &'a Zone,
                     field_idx: u8,
                 }
 
-                impl<'a> serde::ser::SeqVisitor for Visitor<'a> {
+                impl<'a> serde::ser::MapVisitor for Visitor<'a> {
                     fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
                         where S: serde::Serializer,
                     {
                         match self.field_idx {
                             0 => {
                                 self.field_idx += 1;
-                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(self.t.0))))
+                                Ok(Some(try!(serializer.serialize_struct_elt("side",
+                                                                             self.t.side))))
                             },
                             1 => {
                                 self.field_idx += 1;
-                                Ok(Some(try!(serializer.serialize_tuple_struct_elt(&self.t.1))))
+                                Ok(Some(try!(serializer.serialize_struct_elt("member_channels",
+                                                                             self.t.member_channels))))
+                            },
+                            2 => {
+                                self.field_idx += 1;
+                                Ok(Some(try!(serializer.serialize_struct_elt("bend_range",
+                                                                             self.t.bend_range))))
                             },
                             _ => Ok(None),
                         }
                     }
 
                     fn len(&self) -> Option<usize> {
-                        Some(2)
+                        Some(3)
                     }
                 }
 
-                serializer.serialize_tuple_struct("Mono", Visitor { t: self, field_idx: 0 })
+                serializer.serialize_struct("Zone", Visitor { t: self, field_idx: 0 })
             }
         }
 
-        impl serde::Deserialize for Mono {
+        impl serde::Deserialize for Zone {
             fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
                 where D: serde::Deserializer,
             {
                 struct Visitor;
 
                 impl serde::de::Visitor for Visitor {
-                    type Value = Mono;
+                    type Value = Zone;
 
-                    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Mono, V::Error>
-                        where V: serde::de::SeqVisitor,
+                    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Zone, V::Error>
+                        where V: serde::de::MapVisitor,
                     {
-                        let kind = try!(visitor.visit());
-                        let notes = try!(visitor.visit());
-
-                        let kind = match kind {
-                            Some(kind) => kind,
-                            None => return Err(serde::de::Error::missing_field("kind")),
-                        };
-
-                        let notes = match notes {
-                            Some(notes) => notes,
-                            None => return Err(serde::de::Error::missing_field("notes")),
-                        };
-
-                        try!(visitor.end());
-
-                        Ok(Mono(kind, notes))
-                    }
-                }
+                        let mut side = None;
+                        let mut member_channels = None;
+                        let mut bend_range = None;
 
-                deserializer.deserialize_tuple_struct("Mono", 2, Visitor)
-            }
-        }
+                        enum Field {
+                            Side,
+                            MemberChannels,
+                            BendRange,
+                        }
 
-        #[test]
-        fn test() {
-            use mode::MonoKind;
-            extern crate serde_json;
+                        impl serde::Deserialize for Field {
+                            fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                                where D: serde::de::Deserializer,
+                            {
+                                struct FieldVisitor;
 
-            let mono = Mono(MonoKind::Retrigger, vec![440.0]);
-            let serialized = serde_json::to_string(&mono).unwrap();
+                                impl serde::de::Visitor for FieldVisitor {
+                                    type Value = Field;
 
-            println!("{}", serialized);
-            assert_eq!("[{\"Retrigger\":[]},[440]]", &serialized);
-            
-            let deserialized: Mono = serde_json::from_str(&serialized).unwrap();
+                                    fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                        where E: serde::de::Error,
+                                    {
+                                        match value {
+                                            "side" => Ok(Field::Side),
+                                            "member_channels" => Ok(Field::MemberChannels),
+                                            "bend_range" => Ok(Field::BendRange),
+                                            _ => Err(serde::de::Error::custom(
+                                                "expected side, member_channels or bend_range"
+                                            )),
+                                        }
+                                    }
+                                }
 
-            println!("{:?}", deserialized);
-            assert_eq!(mono, deserialized);
-        }
+                                deserializer.deserialize(FieldVisitor)
+                            }
+                        }
 
-    }
+                        loop {
+                            match try!(visitor.visit_key()) {
+                                Some(Field::Side) => { side = Some(try!(visitor.visit_value())); },
+                                Some(Field::MemberChannels) => { member_channels = Some(try!(visitor.visit_value())); },
+                                Some(Field::BendRange) => { bend_range = Some(try!(visitor.visit_value())); },
+                                None => { break; }
+                            }
+                        }
 
-    mod poly {
-        use mode::Poly;
-        use super::super::serde;
+                        let side = match side {
+                            Some(side) => side,
+                            None => return Err(serde::de::Error::missing_field("side")),
+                        };
 
-        impl serde::Serialize for Poly {
-            fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
-                where S: serde::Serializer,
-            {
-                serializer.serialize_unit_struct("Poly")
-            }
-        }
+                        let member_channels = match member_channels {
+                            Some(member_channels) => member_channels,
+                            None => return Err(serde::de::Error::missing_field("member_channels")),
+                        };
 
-        impl serde::Deserialize for Poly {
-            fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
-                where D: serde::Deserializer,
-            {
-                struct Visitor;
+                        let bend_range = match bend_range {
+                            Some(bend_range) => bend_range,
+                            None => return Err(serde::de::Error::missing_field("bend_range")),
+                        };
 
-                impl serde::de::Visitor for Visitor {
-                    type Value = Poly;
+                        try!(visitor.end());
 
-                    fn visit_unit<E>(&mut self) -> Result<Self::Value, E>
-                        where E: serde::de::Error,
-                    {
-                        Ok(Poly)
+                        Ok(Zone {
+                            side: side,
+                            member_channels: member_channels,
+                            bend_range: bend_range,
+                        })
                     }
                 }
 
-                deserializer.deserialize_unit_struct("Poly", Visitor)
+                static FIELDS: &'static [&'static str] = &[
+                    "side",
+                    "member_channels",
+                    "bend_range",
+                ];
+
+                deserializer.deserialize_struct("Zone", FIELDS, Visitor)
             }
         }
 
@@ -657,136 +4149,119 @@ mod mode {
         fn test() {
             extern crate serde_json;
 
-            let poly = Poly;
-            let serialized = serde_json::to_string(&poly).unwrap();
+            let zone = Zone::upper(7).bend_range(24.5);
+            let serialized = serde_json::to_string(&zone).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("null", &serialized);
-            
-            let deserialized: Poly = serde_json::from_str(&serialized).unwrap();
+            assert_eq!("{\"side\":{\"Upper\":[]},\"member_channels\":7,\"bend_range\":24.5}", serialized);
+
+            let deserialized: Zone = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
-            assert_eq!(poly, deserialized);
+            assert_eq!(zone, deserialized);
         }
     }
 
-    mod dynamic {
-        use mode::Dynamic;
+}
+
+mod tuning {
+
+    mod table {
+        use midi::Key;
+        use tuning::{Table, NUM_KEYS};
         use super::super::serde;
 
-        impl serde::Serialize for Dynamic {
+        impl serde::Serialize for Table {
             fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
                 where S: serde::Serializer,
             {
-                match *self {
-                    Dynamic::Mono(ref m) =>
-                        serializer.serialize_newtype_variant("Dynamic", 0, "Mono", m),
-                    Dynamic::Poly(ref p) =>
-                        serializer.serialize_newtype_variant("Dynamic", 1, "Poly", p),
-                }
+                let hz: Vec<_> = (0..NUM_KEYS).map(|key| self.hz(key as Key)).collect();
+                serializer.serialize_newtype_struct("Table", hz)
             }
         }
 
-        impl serde::Deserialize for Dynamic {
+        impl serde::Deserialize for Table {
             fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
                 where D: serde::Deserializer,
             {
-                enum Variant { Mono, Poly }
-
-                impl serde::de::Deserialize for Variant {
-                    fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
-                        where D: serde::Deserializer,
-                    {
-                        struct VariantVisitor;
-
-                        impl serde::de::Visitor for VariantVisitor {
-                            type Value = Variant;
-
-                            fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
-                                where E: serde::de::Error,
-                            {
-                                match value {
-                                    "Mono" => Ok(Variant::Mono),
-                                    "Poly" => Ok(Variant::Poly),
-                                    _ => Err(serde::de::Error::unknown_field(value)),
-                                }
-                            }
-                        }
-
-                        deserializer.deserialize(VariantVisitor)
-                    }
-                }
-
                 struct Visitor;
 
-                impl serde::de::EnumVisitor for Visitor {
-                    type Value = Dynamic;
+                impl serde::de::Visitor for Visitor {
+                    type Value = Table;
 
-                    fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
-                        where V: serde::de::VariantVisitor,
+                    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
+                        where D: serde::Deserializer,
                     {
-                        match try!(visitor.visit_variant()) {
-                            Variant::Mono => {
-                                let mono = try!(visitor.visit_newtype());
-                                Ok(Dynamic::Mono(mono))
-                            },
-                            Variant::Poly => {
-                                let poly = try!(visitor.visit_newtype());
-                                Ok(Dynamic::Poly(poly))
-                            },
+                        let hz: Vec<Option<f32>> = try!(serde::de::Deserialize::deserialize(deserializer));
+                        if hz.len() != NUM_KEYS {
+                            return Err(serde::de::Error::invalid_length(hz.len()));
+                        }
+                        let mut table = Table::default();
+                        for (key, hz) in hz.into_iter().enumerate() {
+                            table.set_hz(key as Key, hz);
                         }
+                        Ok(table)
                     }
                 }
 
-                const VARIANTS: &'static [&'static str] = &["Mono", "Poly"];
-
-                deserializer.deserialize_enum("Dynamic", VARIANTS, Visitor)
+                deserializer.deserialize_newtype_struct("Table", Visitor)
             }
         }
 
         #[test]
         fn test() {
-            use mode::Poly;
             extern crate serde_json;
 
-            let poly = Dynamic::Poly(Poly);
-            let serialized = serde_json::to_string(&poly).unwrap();
+            let mut table = Table::default();
+            table.set_hz(0, None);
+            table.set_hz(69, Some(432.0));
+            let serialized = serde_json::to_string(&table).unwrap();
 
             println!("{}", serialized);
-            assert_eq!("{\"Poly\":null}", serialized);
-            
-            let deserialized: Dynamic = serde_json::from_str(&serialized).unwrap();
+            assert!(serialized.starts_with("[null,"));
+            assert_eq!(serialized.matches(',').count(), 127);
+            assert!(serialized.contains(",432"));
+
+            let deserialized: Table = serde_json::from_str(&serialized).unwrap();
 
             println!("{:?}", deserialized);
-            assert_eq!(poly, deserialized);
+            assert_eq!(table, deserialized);
+
+            assert!(serde_json::from_str::<Table>("[440]").is_err());
         }
     }
+
 }
 
 mod instrument {
+    use envelope::Adsr;
     use instrument::Instrument;
     use note_freq::NoteFreqGenerator;
     use super::serde;
 
-    impl<M, NFG> serde::Serialize for Instrument<M, NFG>
+    impl<M, NFG, E, G> serde::Serialize for Instrument<M, NFG, E, G>
         where M: serde::Serialize,
+              E: serde::Serialize,
               NFG: serde::Serialize + NoteFreqGenerator,
               NFG::NoteFreq: serde::Serialize,
+              G: serde::Serialize,
     {
         fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
             where S: serde::Serializer,
         {
-            struct Visitor<'a, M: 'a, NFG: 'a>
+            struct Visitor<'a, M: 'a, NFG: 'a, E: 'a, G: 'a>
                 where NFG: NoteFreqGenerator,
             {
-                t: &'a Instrument<M, NFG>,
+                t: &'a Instrument<M, NFG, E, G>,
                 field_idx: u8,
             }
 
-            impl<'a, M, NFG> serde::ser::MapVisitor for Visitor<'a, M, NFG>
+            impl<'a, M, NFG, E, G> serde::ser::MapVisitor for Visitor<'a, M, NFG, E, G>
                 where M: serde::Serialize,
+                      E: serde::Serialize,
                       NFG: serde::Serialize + NoteFreqGenerator,
                       NFG::NoteFreq: serde::Serialize,
+                      G: serde::Serialize,
             {
                 fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
                     where S: serde::Serializer,
@@ -810,18 +4285,46 @@ mod instrument {
                         },
                         4 => {
                             self.field_idx += 1;
-                            Ok(Some(try!(serializer.serialize_struct_elt("attack_ms", self.t.attack_ms))))
+                            Ok(Some(try!(serializer.serialize_struct_elt("envelope", &self.t.envelope))))
                         },
                         5 => {
                             self.field_idx += 1;
-                            Ok(Some(try!(serializer.serialize_struct_elt("release_ms", self.t.release_ms))))
+                            Ok(Some(try!(serializer.serialize_struct_elt("pan_law", self.t.pan_law))))
+                        },
+                        6 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("key_track", self.t.key_track))))
+                        },
+                        7 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("pitch_bend", self.t.pitch_bend))))
+                        },
+                        8 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("mpe_zone", self.t.mpe_zone))))
+                        },
+                        9 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("pressure_to_velocity", self.t.pressure_to_velocity))))
+                        },
+                        10 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("rng", &self.t.rng))))
+                        },
+                        11 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("tuning", &self.t.tuning))))
+                        },
+                        12 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("note_match", self.t.note_match))))
                         },
                         _ => Ok(None),
                     }
                 }
 
                 fn len(&self) -> Option<usize> {
-                    Some(6)
+                    Some(13)
                 }
             }
 
@@ -829,37 +4332,61 @@ mod instrument {
         }
     }
 
-    impl<M, NFG> serde::Deserialize for Instrument<M, NFG>
+    /// Instruments serialized before the `envelope` field was added, which instead have
+    /// `attack_ms` and `release_ms` fields, are deserialized with an `Adsr` that has the given
+    /// attack and release. All other fields that such instruments lack take their default value.
+    impl<M, NFG, E, G> serde::Deserialize for Instrument<M, NFG, E, G>
         where M: serde::Deserialize,
+              E: serde::Deserialize + From<Adsr>,
               NFG: serde::Deserialize + NoteFreqGenerator,
               NFG::NoteFreq: serde::Deserialize,
+              G: serde::Deserialize + Default,
     {
         fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
             where D: serde::Deserializer,
         {
             use std;
 
-            struct Visitor<M, NFG> {
+            struct Visitor<M, NFG, E, G> {
                 mode: std::marker::PhantomData<M>,
                 note_freq_gen: std::marker::PhantomData<NFG>,
+                envelope: std::marker::PhantomData<E>,
+                rng: std::marker::PhantomData<G>,
             };
 
-            impl<M, NFG> serde::de::Visitor for Visitor<M, NFG>
+            impl<M, NFG, E, G> serde::de::Visitor for Visitor<M, NFG, E, G>
                 where M: serde::Deserialize,
+                      E: serde::Deserialize + From<Adsr>,
                       NFG: serde::Deserialize + NoteFreqGenerator,
                       NFG::NoteFreq: serde::Deserialize,
+                      G: serde::Deserialize + Default,
             {
-                type Value = Instrument<M, NFG>;
+                type Value = Instrument<M, NFG, E, G>;
 
-                fn visit_map<V>(&mut self, mut visitor: V) -> Result<Instrument<M, NFG>, V::Error>
+                fn visit_map<V>(&mut self, mut visitor: V) -> Result<Instrument<M, NFG, E, G>, V::Error>
                     where V: serde::de::MapVisitor,
                 {
+                    use bend;
+                    use midi;
+                    use mode;
+                    use mpe;
+                    use pan;
+                    use tuning;
                     use voice;
 
                     let mut mode = None;
                     let mut voices = None;
                     let mut detune = None;
                     let mut note_freq_gen = None;
+                    let mut envelope = None;
+                    let mut pan_law = None;
+                    let mut key_track = None;
+                    let mut pitch_bend = None;
+                    let mut mpe_zone = None;
+                    let mut pressure_to_velocity = None;
+                    let mut rng = None;
+                    let mut tuning = None;
+                    let mut note_match = None;
                     let mut attack_ms = None;
                     let mut release_ms = None;
 
@@ -868,6 +4395,15 @@ mod instrument {
                         Voices,
                         Detune,
                         NoteFreqGen,
+                        Envelope,
+                        PanLaw,
+                        KeyTrack,
+                        PitchBend,
+                        MpeZone,
+                        PressureToVelocity,
+                        Rng,
+                        Tuning,
+                        NoteMatch,
                         AttackMs,
                         ReleaseMs,
                     }
@@ -889,10 +4425,21 @@ mod instrument {
                                         "voices" => Ok(Field::Voices),
                                         "detune" => Ok(Field::Detune),
                                         "note_freq_gen" => Ok(Field::NoteFreqGen),
+                                        "envelope" => Ok(Field::Envelope),
+                                        "pan_law" => Ok(Field::PanLaw),
+                                        "key_track" => Ok(Field::KeyTrack),
+                                        "pitch_bend" => Ok(Field::PitchBend),
+                                        "mpe_zone" => Ok(Field::MpeZone),
+                                        "pressure_to_velocity" => Ok(Field::PressureToVelocity),
+                                        "rng" => Ok(Field::Rng),
+                                        "tuning" => Ok(Field::Tuning),
+                                        "note_match" => Ok(Field::NoteMatch),
                                         "attack_ms" => Ok(Field::AttackMs),
                                         "release_ms" => Ok(Field::ReleaseMs),
                                         _ => Err(serde::de::Error::custom("expected mode, voices, \
-                                        detune, note_freq_gen, attack_ms or release_ms")),
+                                        detune, note_freq_gen, envelope, pan_law, key_track, \
+                                        pitch_bend, mpe_zone, pressure_to_velocity, rng, tuning, \
+                                        note_match, attack_ms or release_ms")),
                                     }
                                 }
                             }
@@ -907,6 +4454,17 @@ mod instrument {
                             Some(Field::Voices)      => { voices = Some(try!(visitor.visit_value())); },
                             Some(Field::Detune)      => { detune = Some(try!(visitor.visit_value())); },
                             Some(Field::NoteFreqGen) => { note_freq_gen = Some(try!(visitor.visit_value())); },
+                            Some(Field::Envelope)    => { envelope = Some(try!(visitor.visit_value())); },
+                            Some(Field::PanLaw)      => { pan_law = Some(try!(visitor.visit_value())); },
+                            Some(Field::KeyTrack)    => { key_track = Some(try!(visitor.visit_value())); },
+                            Some(Field::PitchBend)   => { pitch_bend = Some(try!(visitor.visit_value())); },
+                            Some(Field::MpeZone)     => { mpe_zone = Some(try!(visitor.visit_value())); },
+                            Some(Field::PressureToVelocity) => {
+                                pressure_to_velocity = Some(try!(visitor.visit_value()));
+                            },
+                            Some(Field::Rng)         => { rng = Some(try!(visitor.visit_value())); },
+                            Some(Field::Tuning)      => { tuning = Some(try!(visitor.visit_value())); },
+                            Some(Field::NoteMatch)   => { note_match = Some(try!(visitor.visit_value())); },
                             Some(Field::AttackMs)    => { attack_ms = Some(try!(visitor.visit_value())); },
                             Some(Field::ReleaseMs)   => { release_ms = Some(try!(visitor.visit_value())); },
                             None => { break; }
@@ -933,25 +4491,47 @@ mod instrument {
                         None => return Err(serde::de::Error::missing_field("note_freq_gen")),
                     };
 
-                    let attack_ms = match attack_ms {
-                        Some(attack_ms) => attack_ms,
-                        None => return Err(serde::de::Error::missing_field("attack_ms")),
+                    let envelope = match envelope {
+                        Some(envelope) => envelope,
+                        None => {
+                            let mut adsr = Adsr::default();
+                            adsr.attack_ms = attack_ms.unwrap_or(adsr.attack_ms);
+                            adsr.release_ms = release_ms.unwrap_or(adsr.release_ms);
+                            E::from(adsr)
+                        },
                     };
 
-                    let release_ms = match release_ms {
-                        Some(release_ms) => release_ms,
-                        None => return Err(serde::de::Error::missing_field("release_ms")),
-                    };
+                    let pan_law = pan_law.unwrap_or_else(pan::Law::default);
+                    let key_track = key_track.unwrap_or(None);
+                    let pitch_bend = pitch_bend.unwrap_or_else(bend::PitchBend::default);
+                    let mpe_zone = mpe_zone.unwrap_or(None);
+                    let pressure_to_velocity = pressure_to_velocity.unwrap_or(0.0);
+                    let rng = rng.unwrap_or_else(G::default);
+                    let tuning = tuning.unwrap_or_else(tuning::Table::default);
+                    let note_match = note_match.unwrap_or_else(mode::NoteMatch::default);
 
                     try!(visitor.end());
 
                     Ok(Instrument {
                         mode: mode,
-                        voices: vec![voice::Voice { note: None, playhead: 0 }; voices],
+                        voices: vec![voice::Voice::new(); voices],
                         detune: detune,
+                        rng: rng,
                         note_freq_gen: note_freq_gen,
-                        attack_ms: attack_ms,
-                        release_ms: release_ms,
+                        envelope: envelope,
+                        pan_law: pan_law,
+                        key_track: key_track,
+                        pitch_bend: pitch_bend,
+                        mpe_zone: mpe_zone,
+                        mpe_expression: [mpe::Expression::default(); 16],
+                        pressure_to_velocity: pressure_to_velocity,
+                        tuning: tuning,
+                        note_match: note_match,
+                        pedals: mode::Pedals::default(),
+                        frame: 0,
+                        note_count: 0,
+                        events: std::collections::VecDeque::new(),
+                        midi_parser: midi::Parser::new(),
                     })
                 }
             }
@@ -961,13 +4541,22 @@ mod instrument {
                 "voices",
                 "detune",
                 "note_freq_gen",
-                "attack_ms",
-                "release_ms",
+                "envelope",
+                "pan_law",
+                "key_track",
+                "pitch_bend",
+                "mpe_zone",
+                "pressure_to_velocity",
+                "rng",
+                "tuning",
+                "note_match",
             ];
 
             let visitor = Visitor {
                 mode: std::marker::PhantomData,
                 note_freq_gen: std::marker::PhantomData,
+                envelope: std::marker::PhantomData,
+                rng: std::marker::PhantomData,
             };
             deserializer.deserialize_struct("Instrument", FIELDS, visitor)
         }
@@ -975,25 +4564,55 @@ mod instrument {
 
     #[test]
     fn test() {
-        use mode::Poly;
+        use bend::PitchBend;
+        use midi;
+        use mode::{NoteMatch, Pedals, Poly};
+        use mpe;
+        use note_freq::DetuneRng;
+        use pan;
+        use tuning;
         extern crate serde_json;
 
+        let mut table = tuning::Table::default();
+        table.set_hz(69, Some(432.0));
         let instrument = Instrument {
-            mode: Poly,
+            mode: Poly::default(),
             voices: vec![],
             detune: 0.25,
+            rng: DetuneRng::new(7),
             note_freq_gen: (),
-            attack_ms: 10.0.into(),
-            release_ms: 100.0.into(),
+            envelope: Adsr::new(10.0, 50.0, 0.5, 100.0),
+            pan_law: pan::Law::Linear,
+            key_track: Some(pan::KeyTrack::new(60.5, 24.5)),
+            pitch_bend: PitchBend::new(12.5),
+            mpe_zone: Some(mpe::Zone::lower(15)),
+            mpe_expression: [mpe::Expression::default(); 16],
+            pressure_to_velocity: 0.5,
+            tuning: table,
+            note_match: NoteMatch::Cents(2.5),
+            pedals: Pedals::default(),
+            frame: 0,
+            note_count: 0,
+            events: std::collections::VecDeque::new(),
+            midi_parser: midi::Parser::new(),
         };
         let serialized = serde_json::to_string(&instrument).unwrap();
 
         println!("{}", serialized);
-        assert_eq!("{\"mode\":null,\"voices\":0,\"detune\":0.25,\"note_freq_gen\":null,\"attack_ms\":10,\"release_ms\":100}", serialized);
+        let table = serde_json::to_string(&table).unwrap();
+        assert_eq!("{\"mode\":{\"Released\":[]},\"voices\":0,\"detune\":0.25,\"note_freq_gen\":null,\"envelope\":{\"attack_ms\":10.0,\"attack_curve\":{\"Linear\":[]},\"decay_ms\":50.0,\"decay_curve\":{\"Linear\":[]},\"sustain\":0.5,\"release_ms\":100.0,\"release_curve\":{\"Linear\":[]}},\"pan_law\":{\"Linear\":[]},\"key_track\":{\"center\":60.5,\"semitones\":24.5},\"pitch_bend\":{\"range\":12.5,\"smoothing_ms\":0.0},\"mpe_zone\":{\"side\":{\"Lower\":[]},\"member_channels\":15,\"bend_range\":48.0},\"pressure_to_velocity\":0.5,\"rng\":[1496452567,1674306020,4097599004,72105175],\"tuning\":".to_string() + &table + ",\"note_match\":{\"Cents\":2.5}}", serialized);
         
         let deserialized: Instrument<Poly, ()> = serde_json::from_str(&serialized).unwrap();
 
         println!("{:?}", deserialized);
         assert_eq!(instrument, deserialized);
+
+        // An instrument serialized before it had an envelope.
+        let old = "{\"mode\":null,\"voices\":0,\"detune\":0.25,\"note_freq_gen\":null,\"attack_ms\":10.0,\"release_ms\":100.0}";
+        let deserialized: Instrument<Poly, ()> = serde_json::from_str(old).unwrap();
+        let mut expected = Instrument::new(Poly::default(), ()).fade(10.0, 100.0);
+        expected.voices = vec![];
+        expected.detune = 0.25;
+        assert_eq!(expected, deserialized);
     }
 }
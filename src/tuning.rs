@@ -0,0 +1,535 @@
+//! Alternative tunings described by Scala scale (`.scl`) and keyboard mapping (`.kbm`) files.
+//!
+//! A `Scale` and a `KeyboardMapping` are combined into a `Table` holding the frequency of each
+//! MIDI key, which the `Instrument` uses to convert the key of each MIDI note into a `NoteHz`.
+
+use midi::{self, Key};
+use pitch;
+use std;
+use unit::NoteHz;
+
+/// The number of MIDI keys.
+pub const NUM_KEYS: usize = 128;
+
+/// The MIDI Tuning Standard frequency data indicating that a key should not be retuned.
+const MTS_NO_CHANGE: [u8; 3] = [0x7F, 0x7F, 0x7F];
+
+/// A scale parsed from the contents of a Scala `.scl` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    /// The description of the scale given on the first line of the file.
+    pub description: String,
+    /// The pitch of each degree of the scale above the unison in cents, in the order given.
+    ///
+    /// The unison is implied and the last degree is the interval at which the scale repeats
+    /// (usually the octave).
+    pub degrees: Vec<f64>,
+}
+
+/// A mapping of MIDI keys to scale degrees parsed from the contents of a Scala `.kbm` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    /// The first key to be retuned.
+    pub first_key: Key,
+    /// The last key to be retuned.
+    pub last_key: Key,
+    /// The key to which the unison of the scale is mapped.
+    pub middle_key: Key,
+    /// The key whose frequency is given by `reference_hz`.
+    pub reference_key: Key,
+    /// The frequency of the `reference_key`.
+    pub reference_hz: f64,
+    /// The scale degree at which the mapping repeats, or `0` to repeat at the last degree.
+    pub octave_degree: usize,
+    /// The scale degree played by each key of a single repetition of the mapping, starting at the
+    /// `middle_key`, where `None` leaves the key unmapped.
+    ///
+    /// If empty, successive keys play successive scale degrees.
+    pub map: Vec<Option<usize>>,
+}
+
+/// The frequency of each MIDI key.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Table {
+    hz: [Option<NoteHz>; NUM_KEYS],
+}
+
+/// Errors that may occur while parsing a Scala file or building a `Table`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The file ended before all of its expected values were found.
+    UnexpectedEnd,
+    /// The line with the given (one-based) number did not contain a valid value.
+    InvalidLine(usize, String),
+    /// The reference key of the `KeyboardMapping` is not mapped to a scale degree.
+    UnmappedReferenceKey,
+}
+
+
+impl Scale {
+    /// Parse the contents of a Scala `.scl` file.
+    pub fn parse(scl: &str) -> Result<Self, Error> {
+        let mut lines = scl.lines().enumerate().filter(|&(_, line)| !line.starts_with('!'));
+
+        // The description may be blank, so it is taken before skipping blank lines.
+        let description = match lines.next() {
+            Some((_, line)) => line.trim().to_string(),
+            None => return Err(Error::UnexpectedEnd),
+        };
+        let mut values = lines.filter_map(|(idx, line)| {
+            line.split_whitespace().next().map(|value| (idx + 1, value))
+        });
+
+        let num_degrees = match values.next() {
+            Some((line, value)) => value.parse::<usize>().map_err(|_| invalid(line, value))?,
+            None => return Err(Error::UnexpectedEnd),
+        };
+        // The count is untrusted, so the degrees are not preallocated.
+        let mut degrees = Vec::new();
+        for _ in 0..num_degrees {
+            match values.next() {
+                Some((line, value)) => {
+                    let cents = parse_cents(value).ok_or_else(|| invalid(line, value))?;
+                    degrees.push(cents);
+                },
+                None => return Err(Error::UnexpectedEnd),
+            }
+        }
+
+        Ok(Scale { description, degrees })
+    }
+
+    /// Twelve tone equal temperament.
+    pub fn equal_temperament() -> Self {
+        Scale {
+            description: "12 tone equal temperament".to_string(),
+            degrees: (1..13).map(|degree| degree as f64 * 100.0).collect(),
+        }
+    }
+
+    /// The pitch of the given degree in cents above the unison, where degrees beyond the last
+    /// degree continue into the following repetitions of the scale.
+    pub fn cents(&self, degree: i64) -> f64 {
+        let len = self.degrees.len() as i64;
+        if len == 0 {
+            return 0.0;
+        }
+        let period = self.degrees[self.degrees.len() - 1];
+        let repetition = degree.div_euclid(len);
+        let idx = degree.rem_euclid(len) as usize;
+        let cents = if idx == 0 { 0.0 } else { self.degrees[idx - 1] };
+        repetition as f64 * period + cents
+    }
+}
+
+impl KeyboardMapping {
+    /// Parse the contents of a Scala `.kbm` file.
+    pub fn parse(kbm: &str) -> Result<Self, Error> {
+        let mut values = kbm.lines().enumerate()
+            .filter(|&(_, line)| !line.starts_with('!'))
+            .filter_map(|(idx, line)| line.split_whitespace().next().map(|value| (idx + 1, value)));
+
+        fn next<'a, I, T>(values: &mut I) -> Result<T, Error>
+            where I: Iterator<Item=(usize, &'a str)>,
+                  T: std::str::FromStr,
+        {
+            match values.next() {
+                Some((line, value)) => value.parse().map_err(|_| invalid(line, value)),
+                None => Err(Error::UnexpectedEnd),
+            }
+        }
+
+        // A single repetition of the mapping cannot usefully span more than every key.
+        let size = match values.next() {
+            Some((line, value)) => match value.parse::<usize>() {
+                Ok(size) if size <= NUM_KEYS => size,
+                _ => return Err(invalid(line, value)),
+            },
+            None => return Err(Error::UnexpectedEnd),
+        };
+        let first_key = next(&mut values)?;
+        let last_key = next(&mut values)?;
+        let middle_key = next(&mut values)?;
+        let reference_key = next(&mut values)?;
+        let reference_hz = next(&mut values)?;
+        let octave_degree = next(&mut values)?;
+        let mut map = Vec::with_capacity(size);
+        for _ in 0..size {
+            match values.next() {
+                Some((_, "x")) | Some((_, "X")) => map.push(None),
+                Some((line, value)) =>
+                    map.push(Some(value.parse().map_err(|_| invalid(line, value))?)),
+                None => return Err(Error::UnexpectedEnd),
+            }
+        }
+
+        Ok(KeyboardMapping {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_hz,
+            octave_degree,
+            map,
+        })
+    }
+
+    /// The pitch of the given key in cents above the `middle_key` within the given scale, or
+    /// `None` if the key is unmapped.
+    pub fn cents(&self, scale: &Scale, key: Key) -> Option<f64> {
+        let offset = key as i64 - self.middle_key as i64;
+        if self.map.is_empty() {
+            return Some(scale.cents(offset));
+        }
+        let size = self.map.len() as i64;
+        let repetition = offset.div_euclid(size);
+        let octave_degree = match self.octave_degree {
+            0 => scale.degrees.len(),
+            degree => degree,
+        };
+        self.map[offset.rem_euclid(size) as usize].map(|degree| {
+            repetition as f64 * scale.cents(octave_degree as i64) + scale.cents(degree as i64)
+        })
+    }
+}
+
+impl Default for KeyboardMapping {
+    /// Maps successive keys to successive scale degrees with the unison on middle C (key `60`)
+    /// and A4 (key `69`) at 440hz.
+    fn default() -> Self {
+        KeyboardMapping {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 69,
+            reference_hz: 440.0,
+            octave_degree: 0,
+            map: vec![],
+        }
+    }
+}
+
+impl Table {
+    /// The frequency of each key in twelve tone equal temperament where key `69` is A4 (440hz).
+    pub fn equal_temperament() -> Self {
+        let mut hz = [None; NUM_KEYS];
+        for (key, hz) in hz.iter_mut().enumerate() {
+            *hz = Some(midi::key_to_hz(key as Key));
+        }
+        Table { hz }
+    }
+
+    /// The frequency of each key when the given `scale` is mapped to the keyboard by `mapping`.
+    ///
+    /// Keys outside of the mapping's range keep their equal temperament frequencies, while
+    /// unmapped keys within the range have no frequency.
+    pub fn from_scala(scale: &Scale, mapping: &KeyboardMapping) -> Result<Self, Error> {
+        let reference_cents = match mapping.cents(scale, mapping.reference_key) {
+            Some(cents) => cents,
+            None => return Err(Error::UnmappedReferenceKey),
+        };
+        let mut table = Table::equal_temperament();
+        for key in mapping.first_key..mapping.last_key.saturating_add(1).min(NUM_KEYS as Key) {
+            table.hz[key as usize] = mapping.cents(scale, key).map(|cents| {
+                let hz = mapping.reference_hz * ((cents - reference_cents) / 1200.0).exp2();
+                hz as NoteHz
+            });
+        }
+        Ok(table)
+    }
+
+    /// The frequency of the given key, or `None` if the key is unmapped.
+    pub fn hz(&self, key: Key) -> Option<NoteHz> {
+        self.hz.get(key as usize).and_then(|&hz| hz)
+    }
+
+    /// Set the frequency of the given key, where `None` leaves the key unmapped.
+    pub fn set_hz(&mut self, key: Key, hz: Option<NoteHz>) {
+        if let Some(key_hz) = self.hz.get_mut(key as usize) {
+            *key_hz = hz;
+        }
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table::equal_temperament()
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Error::UnexpectedEnd => write!(f, "the file ended before all values were found"),
+            Error::InvalidLine(line, ref value) =>
+                write!(f, "invalid value \"{}\" on line {}", value, line),
+            Error::UnmappedReferenceKey => write!(f, "the reference key is not mapped"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+
+/// Parse a MIDI Tuning Standard system exclusive message into the new frequency of each key that
+/// it retunes.
+///
+/// Bulk tuning dumps and single note tuning changes (with or without a bank) are supported, while
+/// the tuning program and device are ignored. The leading `0xF0` and trailing `0xF7` bytes of the
+/// message may be included or omitted.
+///
+/// Returns `None` if the message is not a supported MIDI Tuning Standard message.
+pub fn parse_mts(sysex: &[u8]) -> Option<Vec<(Key, NoteHz)>> {
+    let sysex = sysex.strip_prefix(&[0xF0]).unwrap_or(sysex);
+    let sysex = sysex.strip_suffix(&[0xF7]).unwrap_or(sysex);
+    match *sysex {
+        // A bulk tuning dump, in which the name of the tuning precedes the data of every key.
+        [0x7E, _, 0x08, 0x01, _program, ref rest @ ..] => {
+            let data = rest.get(16..16 + 3 * NUM_KEYS)?;
+            let retunings = data.chunks(3).enumerate()
+                .filter_map(|(key, data)| mts_hz(data).map(|hz| (key as Key, hz)))
+                .collect();
+            Some(retunings)
+        },
+        // Single note tuning changes.
+        [0x7F, _, 0x08, 0x02, _, num_keys, ref rest @ ..] |
+        [0x7E | 0x7F, _, 0x08, 0x07, _, _, num_keys, ref rest @ ..] => {
+            let data = rest.get(..4 * num_keys as usize)?;
+            let retunings = data.chunks(4)
+                .filter_map(|data| mts_hz(&data[1..]).map(|hz| (data[0], hz)))
+                .collect();
+            Some(retunings)
+        },
+        _ => None,
+    }
+}
+
+
+/// The frequency given by three bytes of MIDI Tuning Standard frequency data, or `None` if the
+/// data indicates no change.
+///
+/// The first byte is a MIDI key in equal temperament and the remaining bytes are a 14-bit
+/// fraction of a semitone above it.
+fn mts_hz(data: &[u8]) -> Option<NoteHz> {
+    if data == MTS_NO_CHANGE {
+        return None;
+    }
+    let fraction = ((data[1] as u16) << 7 | data[2] as u16) as f32 / 16384.0;
+    Some(pitch::Step(data[0] as f32 + fraction).hz())
+}
+
+/// An `InvalidLine` error for the given line number and value.
+fn invalid(line: usize, value: &str) -> Error {
+    Error::InvalidLine(line, value.to_string())
+}
+
+/// Parse a Scala pitch value, which is in cents if it contains a period and is otherwise a ratio
+/// such as `3/2` or `2`.
+fn parse_cents(value: &str) -> Option<f64> {
+    if value.contains('.') {
+        return value.parse().ok();
+    }
+    let mut parts = value.splitn(2, '/');
+    let numerator: f64 = match parts.next().map(str::parse) {
+        Some(Ok(numerator)) => numerator,
+        _ => return None,
+    };
+    let denominator: f64 = match parts.next().map(str::parse) {
+        Some(Ok(denominator)) => denominator,
+        Some(Err(_)) => return None,
+        None => 1.0,
+    };
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return None;
+    }
+    Some(1200.0 * (numerator / denominator).log2())
+}
+
+
+#[cfg(test)]
+mod test {
+    use midi::{self, Key};
+    use pitch;
+    use super::{Error, KeyboardMapping, Scale, Table, NUM_KEYS};
+    use super::parse_mts;
+    use unit::NoteHz;
+
+    const PYTHAGOREAN_FIFTH_CENTS: f64 = 701.955_000_865_387_4;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    fn assert_retunings(retunings: Option<Vec<(Key, NoteHz)>>, expected: &[(Key, NoteHz)]) {
+        let retunings = retunings.expect("expected an MTS message");
+        assert_eq!(retunings.len(), expected.len());
+        for (&(key, hz), &(expected_key, expected_hz)) in retunings.iter().zip(expected) {
+            assert_eq!(key, expected_key);
+            assert!((hz - expected_hz).abs() < 1e-3, "key {}: {} != {}", key, hz, expected_hz);
+        }
+    }
+
+    #[test]
+    fn scale_with_huge_degree_count() {
+        let scl = "huge\n18446744073709551615\n100.0\n";
+        assert_eq!(Scale::parse(scl), Err(Error::UnexpectedEnd));
+    }
+
+    #[test]
+    fn mapping_with_huge_size() {
+        let kbm = "18446744073709551615\n0\n127\n60\n69\n440.0\n0\n0\n";
+        assert_eq!(KeyboardMapping::parse(kbm),
+                   Err(Error::InvalidLine(1, "18446744073709551615".to_string())));
+        let kbm = "129\n0\n127\n60\n69\n440.0\n0\n";
+        assert_eq!(KeyboardMapping::parse(kbm), Err(Error::InvalidLine(1, "129".to_string())));
+    }
+
+    #[test]
+    fn mapping_missing_entries() {
+        let kbm = "3\n0\n127\n60\n69\n440.0\n0\n0\n1\n";
+        assert_eq!(KeyboardMapping::parse(kbm), Err(Error::UnexpectedEnd));
+    }
+
+    #[test]
+    fn scale_with_cents_ratios_and_comments() {
+        let scl = "! pythagorean.scl\n!\n\n 4\n! degrees\n 100.0 cents\n 3/2\n 700.\n 2\n";
+        let scale = Scale::parse(scl).unwrap();
+        assert_eq!(scale.description, "");
+        assert_eq!(scale.degrees.len(), 4);
+        assert_close(scale.degrees[0], 100.0);
+        assert_close(scale.degrees[1], PYTHAGOREAN_FIFTH_CENTS);
+        assert_close(scale.degrees[2], 700.0);
+        assert_close(scale.degrees[3], 1200.0);
+    }
+
+    #[test]
+    fn scale_with_invalid_degree() {
+        let scl = "bad\n2\n3/0\n2/1\n";
+        assert_eq!(Scale::parse(scl), Err(Error::InvalidLine(3, "3/0".to_string())));
+    }
+
+    #[test]
+    fn mapping_with_unmapped_keys_and_octave_degree() {
+        // Map the seven white keys of each octave to a diatonic scale within 12-TET.
+        let kbm = "! white.kbm\n12\n0\n127\n60\n69\n440.0\n12\n\
+                   0\nx\n2\nx\n4\n5\nx\n7\nx\n9\nx\n11\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        assert_eq!(mapping.octave_degree, 12);
+        assert_eq!(mapping.map.len(), 12);
+        assert_eq!(mapping.map[1], None);
+        assert_eq!(mapping.map[2], Some(2));
+
+        let scale = Scale::equal_temperament();
+        assert_eq!(mapping.cents(&scale, 61), None);
+        assert_eq!(mapping.cents(&scale, 59), Some(-100.0));
+        assert_eq!(mapping.cents(&scale, 72), Some(1200.0));
+
+        // A seven key mapping repeating at the twelfth degree puts the octave on the fifth key.
+        let kbm = "7\n0\n127\n60\n60\n261.6\n12\n0\n2\n4\n5\n7\n9\n11\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        assert_eq!(mapping.cents(&scale, 66), Some(1100.0));
+        assert_eq!(mapping.cents(&scale, 67), Some(1200.0));
+        assert_eq!(mapping.cents(&scale, 53), Some(-1200.0));
+    }
+
+    #[test]
+    fn linear_mapping() {
+        let kbm = "0\n0\n127\n60\n69\n440.0\n0\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        assert_eq!(mapping, KeyboardMapping::default());
+        let scale = Scale::equal_temperament();
+        assert_eq!(mapping.cents(&scale, 48), Some(-1200.0));
+        assert_eq!(mapping.cents(&scale, 73), Some(1300.0));
+    }
+
+    #[test]
+    fn equal_temperament_table() {
+        let scale = Scale::equal_temperament();
+        let table = Table::from_scala(&scale, &KeyboardMapping::default()).unwrap();
+        for key in 0..128 {
+            let expected = midi::key_to_hz(key);
+            let hz = table.hz(key).unwrap();
+            assert!((hz - expected).abs() / expected < 1e-4, "key {}: {} != {}", key, hz, expected);
+        }
+    }
+
+    #[test]
+    fn pythagorean_table() {
+        let scale = Scale::parse("fifths\n2\n3/2\n2/1\n").unwrap();
+        let kbm = "0\n0\n127\n60\n60\n440.0\n0\n";
+        let table = Table::from_scala(&scale, &KeyboardMapping::parse(kbm).unwrap()).unwrap();
+        assert_eq!(table.hz(60), Some(440.0));
+        assert_eq!(table.hz(61), Some(660.0));
+        assert_eq!(table.hz(62), Some(880.0));
+        assert_eq!(table.hz(59), Some(330.0));
+    }
+
+    #[test]
+    fn unmapped_reference_key() {
+        let kbm = "2\n0\n127\n60\n61\n440.0\n0\n0\nx\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        let table = Table::from_scala(&Scale::equal_temperament(), &mapping);
+        assert_eq!(table, Err(Error::UnmappedReferenceKey));
+    }
+
+    /// A bulk tuning dump in which key `0` is unchanged, key `69` is a quarter tone sharp and
+    /// every other key is raised by a semitone.
+    fn bulk_dump() -> Vec<u8> {
+        let mut sysex = vec![0xF0, 0x7E, 0x00, 0x08, 0x01, 0x03];
+        sysex.extend(b"quarter tone    ");
+        sysex.extend(&[0x7F, 0x7F, 0x7F]);
+        for key in 1..NUM_KEYS as u8 {
+            if key == 69 {
+                sysex.extend(&[69, 0x40, 0x00]);
+            } else {
+                sysex.extend(&[(key + 1).min(127), 0x00, 0x00]);
+            }
+        }
+        // The checksum is ignored.
+        sysex.extend(&[0x00, 0xF7]);
+        sysex
+    }
+
+    #[test]
+    fn mts_bulk_dump() {
+        let expected: Vec<_> = (1..NUM_KEYS as Key).map(|key| match key {
+            69 => (key, pitch::Step(69.5).hz()),
+            _ => (key, midi::key_to_hz((key + 1).min(127))),
+        }).collect();
+        assert_retunings(parse_mts(&bulk_dump()), &expected);
+    }
+
+    #[test]
+    fn mts_single_note_without_bank() {
+        let sysex = [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 2, 60, 61, 0x00, 0x00, 62, 0x7F, 0x7F, 0x7F,
+                     0xF7];
+        assert_retunings(parse_mts(&sysex), &[(60, midi::key_to_hz(61))]);
+    }
+
+    #[test]
+    fn mts_single_note_with_bank() {
+        // The leading and trailing bytes may be omitted.
+        let sysex = [0x7F, 0x00, 0x08, 0x07, 0x01, 0x02, 1, 69, 69, 0x20, 0x00];
+        assert_retunings(parse_mts(&sysex), &[(69, pitch::Step(69.25).hz())]);
+        let sysex = [0xF0, 0x7E, 0x00, 0x08, 0x07, 0x01, 0x02, 1, 69, 0x7F, 0x7F, 0x7F, 0xF7];
+        assert_retunings(parse_mts(&sysex), &[]);
+    }
+
+    #[test]
+    fn mts_truncated() {
+        let mut sysex = bulk_dump();
+        sysex.truncate(6 + 16 + 3 * 127);
+        assert_eq!(parse_mts(&sysex), None);
+        let sysex = [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 2, 60, 61, 0x00, 0x00, 62, 0xF7];
+        assert_eq!(parse_mts(&sysex), None);
+        assert_eq!(parse_mts(&[0xF0, 0x7F, 0x7F, 0x08, 0xF7]), None);
+    }
+
+    #[test]
+    fn mts_unsupported() {
+        // A general information identity request.
+        assert_eq!(parse_mts(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]), None);
+        // A bulk tuning dump request.
+        assert_eq!(parse_mts(&[0xF0, 0x7E, 0x00, 0x08, 0x00, 0x00, 0xF7]), None);
+    }
+}
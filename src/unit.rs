@@ -3,4 +3,6 @@ use pitch;
 pub type Playhead = u64;
 pub type NoteFreqMulti = f64;
 pub type NoteHz = pitch::calc::Hz;
+pub type NoteId = u64;
 pub type NoteVelocity = f32;
+pub type Pan = f32;
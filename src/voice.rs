@@ -1,6 +1,8 @@
+use envelope::Envelope;
+use mode::NoteOn;
 use note_freq::NoteFreq;
-use std;
-use unit::{NoteHz, NoteVelocity, Playhead};
+use time;
+use unit::{NoteHz, NoteId, NoteVelocity, Pan, Playhead};
 
 /// A single Voice. A Synth may consist of any number of Voices.
 #[derive(Clone, Debug, PartialEq)]
@@ -11,33 +13,114 @@ pub struct Voice<NF> {
     pub note: Option<Note<NF>>,
     /// Number of frames played since the beginning of the note.
     pub playhead: Playhead,
+    /// The amplitude from which the `Voice` is fading into its current note after having stolen
+    /// a sounding note, along with the number of frames faded so far.
+    pub steal_fade: Option<(f32, Playhead)>,
 }
 
 /// Represents an active `Note`, currently being performed by the `Voice`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Note<NF> {
-    /// The current state of the `Note` (`Playing` or `Released`).
+    /// The current state of the `Note` (`Attack`, `Decay`, `Sustain` or `Released`).
     pub state: NoteState,
     /// The note frequency produced by the note frequency generator.
     pub freq: NF,
     /// The hz of the `note_on` event.
     pub hz: NoteHz,
+    /// The unique identifier given with the `note_on` event, if any.
+    pub id: Option<NoteId>,
     /// The velocity of the `note_on` event.
     pub vel: NoteVelocity,
-    /// The time at which the `Note` was constructed.
-    pub time_of_note_on: std::time::Instant,
+    /// The offset of the note's pitch from the hz produced by its `freq`, in cents.
+    pub cents: f32,
+    /// The stereo position of the note from `-1.0` (left) to `1.0` (right).
+    pub pan: Pan,
+    /// The offset of the voice's stereo position from the note's `pan`.
+    pub spread: Pan,
+    /// The offset of the note's pitch from its `hz` in cents, set when the note's key is retuned.
+    pub retune: f32,
+    /// The note's own pitch bend in semitones, applied on top of the bend of every voice.
+    pub bend: f32,
+    /// The pressure applied to the note, from `0.0` to `1.0`.
+    pub pressure: f32,
+    /// The timbre of the note, from `0.0` to `1.0`.
+    pub timbre: f32,
+    /// The amplitude of the note's envelope at the most recently yielded frame.
+    pub amp: f32,
+    /// Whether or not the note's key has been released while the note is held by a pedal.
+    ///
+    /// A sustained note continues playing until the pedal that holds it is released.
+    pub sustained: bool,
+    /// Whether or not the note was held when the sostenuto pedal was pressed, in which case it is
+    /// held by the sostenuto pedal until the pedal is released.
+    pub latched: bool,
+    /// The order of the note's `note_on` among all notes played by the `Instrument`, where notes
+    /// with a lower order are older.
+    pub order: u64,
 }
 
 /// The current state of the Voice's note playback.
+///
+/// A playing note steps through the `Attack`, `Decay` and `Sustain` stages as the `Voice`'s
+/// playhead advances.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum NoteState {
-    /// The note is current playing.
-    Playing,
+    /// The note is playing and its amplitude is rising towards the note velocity.
+    Attack,
+    /// The note is playing and its amplitude is falling towards the sustain level.
+    Decay,
+    /// The note is playing and its amplitude is held at the sustain level.
+    Sustain,
     /// The note has been released and is fading out.
-    Released(Playhead),
+    Released {
+        /// The number of frames played since the note was released.
+        playhead: Playhead,
+        /// The amplitude of the note's envelope at the moment it was released, taken on the first
+        /// released frame.
+        amp: f32,
+    },
+}
+
+/// The duration over which a `Voice` fades from the amplitude of a stolen note into its new note.
+pub const STEAL_FADE_MS: time::calc::Ms = 5.0;
+
+
+/// The amplitude `env_amp` of the envelope, faded from the amplitude of a stolen note if the
+/// `steal_fade` has not yet completed.
+fn steal_fade_amp(env_amp: f32,
+                  steal_fade: Option<(f32, Playhead)>,
+                  sample_hz: time::SampleHz) -> f32
+{
+    match steal_fade {
+        Some((from_amp, fade_playhead)) => {
+            let fade = time::Ms(STEAL_FADE_MS).samples(sample_hz) as Playhead;
+            if fade_playhead < fade {
+                let perc = fade_playhead as f32 / fade as f32;
+                from_amp + (env_amp - from_amp) * perc
+            } else {
+                env_amp
+            }
+        },
+        None => env_amp,
+    }
+}
+
+
+impl NoteState {
+    /// Whether or not the note is still being held (i.e. has not yet been released).
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        !matches!(*self, NoteState::Released { .. })
+    }
 }
 
 
+impl<NF> Default for Voice<NF> {
+    fn default() -> Self {
+        Voice::new()
+    }
+}
+
 impl<NF> Voice<NF> {
 
     /// Constructor for a Voice.
@@ -45,6 +128,36 @@ impl<NF> Voice<NF> {
         Voice {
             note: None,
             playhead: 0,
+            steal_fade: None,
+        }
+    }
+
+    /// A copy of the voice in which the frequency of the current note is converted by `map`.
+    pub fn map_freq<F, NF2>(&self, map: F) -> Voice<NF2>
+        where F: FnOnce(&NF) -> NF2,
+    {
+        let note = self.note.as_ref().map(|note| Note {
+            state: note.state,
+            freq: map(&note.freq),
+            hz: note.hz,
+            id: note.id,
+            vel: note.vel,
+            cents: note.cents,
+            pan: note.pan,
+            spread: note.spread,
+            retune: note.retune,
+            bend: note.bend,
+            pressure: note.pressure,
+            timbre: note.timbre,
+            amp: note.amp,
+            sustained: note.sustained,
+            latched: note.latched,
+            order: note.order,
+        });
+        Voice {
+            note,
+            playhead: self.playhead,
+            steal_fade: self.steal_fade,
         }
     }
 
@@ -56,21 +169,71 @@ impl<NF> Voice<NF> {
 
     /// Trigger playback with the given note, resetting all playheads.
     #[inline]
-    pub fn note_on(&mut self, hz: NoteHz, freq: NF, vel: NoteVelocity) {
+    pub fn note_on(&mut self, note_on: NoteOn, freq: NF) {
+        let amp = self.note.as_ref().map_or(0.0, |note| note.amp);
+        let NoteOn { hz, id, vel, pan, order } = note_on;
         self.note = Some(Note {
-            state: NoteState::Playing,
-            hz: hz,
-            vel: vel,
-            freq: freq,
-            time_of_note_on: std::time::Instant::now(),
+            state: NoteState::Attack,
+            hz,
+            id,
+            vel,
+            cents: 0.0,
+            pan,
+            spread: 0.0,
+            retune: 0.0,
+            bend: 0.0,
+            pressure: 0.0,
+            timbre: 0.0,
+            amp,
+            sustained: false,
+            latched: false,
+            freq,
+            order,
         });
     }
 
+    /// Replace the current note with the given note, resetting the playhead.
+    ///
+    /// If a note was sounding, the `Voice` briefly fades from its current amplitude into the new
+    /// note in order to avoid a click.
+    #[inline]
+    pub fn steal(&mut self, note_on: NoteOn, freq: NF) {
+        // Express the amplitude of the stolen note relative to the velocity of the new note.
+        self.steal_fade = match self.note {
+            Some(ref note) if note_on.vel > 0.0 => Some((note.vel * note.amp / note_on.vel, 0)),
+            _ => None,
+        };
+        self.reset_playhead();
+        self.note_on(note_on, freq);
+    }
+
+    /// Offset the pitch of the current note by the given number of `cents` and its stereo
+    /// position by the given `spread`.
+    #[inline]
+    pub fn spread(&mut self, cents: f32, spread: Pan) {
+        if let Some(ref mut note) = self.note {
+            note.cents = cents;
+            note.spread = spread;
+        }
+    }
+
     /// Release playback of the current not eif there is one.
     #[inline]
     pub fn note_off(&mut self) {
         if let Some(ref mut note) = self.note {
-            note.state = NoteState::Released(0);
+            if note.state.is_playing() {
+                note.state = NoteState::Released { playhead: 0, amp: note.amp };
+            }
+        }
+    }
+
+    /// Latch the current note to the sostenuto pedal if its key is still held.
+    #[inline]
+    pub fn latch(&mut self) {
+        if let Some(ref mut note) = self.note {
+            if note.state.is_playing() && !note.sustained {
+                note.latched = true;
+            }
         }
     }
 
@@ -79,46 +242,79 @@ impl<NF> Voice<NF> {
     pub fn stop(&mut self) {
         self.note = None;
         self.playhead = 0;
+        self.steal_fade = None;
     }
 
     /// The velocity and frequency of the voice for the next frame.
+    ///
+    /// The note's velocity is raised towards `1.0` by its pressure scaled by the given
+    /// `pressure_to_velocity` amount and is then shaped by the given amplitude `envelope`, while
+    /// its frequency is bent by the given number of semitones.
     #[inline]
-    pub fn next_vel_hz(&mut self, attack: u64, release: u64) -> Option<(NoteVelocity, NoteHz)>
+    pub fn next_vel_hz<E>(&mut self,
+                          envelope: &E,
+                          bend: f32,
+                          pressure_to_velocity: f32,
+                          sample_hz: time::SampleHz) -> Option<(NoteVelocity, NoteHz)>
         where NF: NoteFreq,
+              E: Envelope,
     {
-        // Calculates the current attack amplitude, steps forward the playhead and returns the amp.
-        fn next_attack_amp(playhead: &mut u64, attack: u64) -> f32 {
-            if *playhead < attack {
-                let amp = *playhead as f32 / attack as f32;
-                *playhead += 1;
-                amp
-            } else {
-                1.0
-            }
-        }
-
-        let Voice { ref mut note, ref mut playhead } = *self;
-        match *note {
-            Some(Note { ref mut state, ref mut freq, vel, .. }) => match *state {
-                NoteState::Playing => {
-                    let attack_amp = next_attack_amp(playhead, attack);
-                    let vel = vel * attack_amp;
-                    return Some((vel, freq.next_hz()));
+        let Voice { ref mut note, ref mut playhead, ref mut steal_fade } = *self;
+        if let Some(Note {
+            ref mut state, ref mut freq, ref mut amp, vel, cents, retune, bend: note_bend, pressure,
+            ..
+        }) = *note {
+            let maybe_env_amp = match *state {
+                NoteState::Attack | NoteState::Decay | NoteState::Sustain => {
+                    let (stage, env_amp) = envelope.held(*playhead, sample_hz);
+                    *state = stage;
+                    Some(env_amp)
                 },
-                NoteState::Released(ref mut release_playhead) if *release_playhead < release => {
-                    let attack_amp = next_attack_amp(playhead, attack);
-                    let release_amp = (release - *release_playhead) as f32 / release as f32;
+                NoteState::Released {
+                    playhead: ref mut release_playhead,
+                    amp: ref mut release_amp,
+                } => {
+                    // The note may have been released before any of its held frames were
+                    // rendered, so the amplitude it is released from is taken from the envelope
+                    // on the first released frame, fading from a stolen note if necessary.
+                    if *release_playhead == 0 {
+                        let (_, held_amp) = envelope.held(*playhead, sample_hz);
+                        *release_amp = steal_fade_amp(held_amp, *steal_fade, sample_hz);
+                        *steal_fade = None;
+                    }
+                    let maybe_env_amp =
+                        envelope.released(*release_amp, *release_playhead, sample_hz);
                     *release_playhead += 1;
-                    let vel = vel * attack_amp * release_amp;
-                    return Some((vel, freq.next_hz()));
+                    maybe_env_amp
                 },
-                _ => (),
-            },
-            None => return None,
+            };
+
+            if let Some(env_amp) = maybe_env_amp {
+                *playhead += 1;
+
+                // If the voice stole a sounding note, fade from its amplitude into the new note.
+                *amp = steal_fade_amp(env_amp, *steal_fade, sample_hz);
+                if let Some((from_amp, fade_playhead)) = *steal_fade {
+                    let fade = time::Ms(STEAL_FADE_MS).samples(sample_hz) as Playhead;
+                    *steal_fade = if fade_playhead < fade {
+                        Some((from_amp, fade_playhead + 1))
+                    } else {
+                        None
+                    };
+                }
+
+                let vel = vel + (1.0 - vel) * pressure * pressure_to_velocity;
+                let semitones = (cents + retune) / 100.0 + bend + note_bend;
+                let hz = freq.next_hz() * (semitones / 12.0).exp2();
+                return Some((vel * *amp, hz));
+            }
+        } else {
+            return None;
         }
 
-        // The `NoteState::Released` playhead is out of range, thus the note is finished.
+        // The `Envelope` has finished releasing the note.
         *note = None;
+        *steal_fade = None;
         None
     }
 